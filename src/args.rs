@@ -1,16 +1,34 @@
-use std::fmt;
+use std::{ffi::OsString, fmt, fs, net::SocketAddr, path::PathBuf, str::FromStr, time::Duration};
 
+use anyhow::{anyhow, bail, Error, Result};
 use byte_unit::Byte;
-use clap::{ArgAction, Parser, ValueEnum};
+use clap::{ArgAction, Command, CommandFactory, FromArgMatches, Parser, ValueEnum};
 use duration_string::DurationString;
 use s3::{creds::Credentials, Region};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use tracing::info;
 
 #[derive(Clone, Debug, PartialEq, Parser, Serialize, Deserialize)]
 #[clap(rename_all = "kebab-case")]
 #[serde(rename_all = "camelCase")]
 pub struct Args {
+    /// Scenario file (YAML or JSON) to fill the arguments from;
+    /// CLI flags and environment variables take precedence over it
+    #[arg(long, env = "SOS_CONFIG", value_name = "PATH")]
+    #[serde(skip)]
+    pub config: Option<PathBuf>,
+
+    /// Storage backend: `s3`, `memory`, or `fs:PATH`
+    #[arg(
+        long,
+        env = "SOS_BACKEND",
+        value_name = "SPEC",
+        default_value_t = BackendKind::default(),
+    )]
+    #[serde(default)]
+    pub backend: BackendKind,
+
     #[arg(long, env = "AWS_BUCKET", value_name = "NAME")]
     pub bucket_name: String,
 
@@ -27,6 +45,10 @@ pub struct Args {
     #[serde(default, flatten)]
     pub credentials: CredentialsArgs,
 
+    #[command(flatten)]
+    #[serde(default, flatten)]
+    pub distributed: DistributedArgs,
+
     #[command(flatten)]
     #[serde(default, flatten)]
     pub load_tester: LoadTesterArgs,
@@ -35,27 +57,107 @@ pub struct Args {
     #[serde(default, flatten)]
     pub load_tester_job: LoadTesterJobArgs,
 
+    #[command(flatten)]
+    #[serde(default, flatten)]
+    pub metrics: MetricsArgs,
+
     #[command(flatten)]
     #[serde(default, flatten)]
     pub region: RegionArgs,
 }
 
 impl Args {
+    pub fn try_parse_with_config() -> Result<Self> {
+        Self::try_parse_with_config_from(::std::env::args_os())
+    }
+
+    pub fn try_parse_with_config_from<I, T>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        Self::try_parse_with_config_command(Self::command(), args)
+    }
+
+    /// Parses the arguments with the command, whose arguments may be modified.
+    fn try_parse_with_config_command<I, T>(mut command: Command, args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let args = args.into_iter().collect::<Vec<_>>();
+
+        let matches = command
+            .clone()
+            .ignore_errors(true)
+            .get_matches_from(args.clone());
+        if let Some(path) = matches.get_one::<PathBuf>("config") {
+            for (key, value) in load_scenario(path)? {
+                let id = to_snake_case(&key);
+                if id == "config" || !command.get_arguments().any(|arg| arg.get_id() == &id) {
+                    bail!(
+                        "unknown field in scenario file ({path}): {key}",
+                        path = path.display()
+                    )
+                }
+
+                command = match value {
+                    Value::Null => continue,
+                    Value::Array(values) => {
+                        let values = values
+                            .into_iter()
+                            .map(|value| to_arg_value(&key, value))
+                            .collect::<Result<Vec<_>>>()?;
+                        command.mut_arg(id, |arg| arg.default_values(values).required(false))
+                    }
+                    value => {
+                        let value = to_arg_value(&key, value)?;
+                        command.mut_arg(id, |arg| arg.default_value(value).required(false))
+                    }
+                };
+            }
+        }
+
+        let matches = command.try_get_matches_from(args)?;
+        Self::from_arg_matches(&matches).map_err(Into::into)
+    }
+
+    /// Parses the distributed arguments alone,
+    /// since the workers receive the others from the controller.
+    pub fn try_parse_distributed() -> Result<DistributedArgs> {
+        let matches = Self::command().ignore_errors(true).get_matches();
+        DistributedArgs::from_arg_matches(&matches).map_err(Into::into)
+    }
+
     pub fn print(&self) {
         let Self {
+            config,
+            backend,
             bucket_name,
             bucket_create,
             credentials,
+            distributed,
             load_tester,
             load_tester_job,
+            metrics,
             region,
         } = self;
 
+        info!(
+            "config: {config}",
+            config = config
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_else(|| "None".into(),)
+        );
+        info!("backend: {backend}");
         info!("bucket_name: {bucket_name}");
         info!("bucket_create: {bucket_create}");
         credentials.print();
+        distributed.print();
         load_tester.print();
         load_tester_job.print();
+        metrics.print();
         region.print();
     }
 }
@@ -66,6 +168,109 @@ impl Args {
     }
 }
 
+/// Storage backend to load.
+///
+/// The bucket is a subdirectory of `PATH` on the `fs` backend.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum BackendKind {
+    Fs(PathBuf),
+    Memory,
+    #[default]
+    S3,
+}
+
+impl fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fs(path) => write!(f, "fs:{path}", path = path.display()),
+            Self::Memory => f.write_str("memory"),
+            Self::S3 => f.write_str("s3"),
+        }
+    }
+}
+
+impl FromStr for BackendKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "memory" => Ok(Self::Memory),
+            "s3" => Ok(Self::S3),
+            s => match s.strip_prefix("fs:").map(str::trim) {
+                Some(path) if !path.is_empty() => Ok(Self::Fs(path.into())),
+                _ => bail!("expected s3, memory, or fs:PATH, but given: {s}"),
+            },
+        }
+    }
+}
+
+impl TryFrom<String> for BackendKind {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<BackendKind> for String {
+    fn from(value: BackendKind) -> Self {
+        value.to_string()
+    }
+}
+
+fn load_scenario(path: &PathBuf) -> Result<Map<String, Value>> {
+    let content = fs::read_to_string(path).map_err(|error| {
+        anyhow!(
+            "failed to read scenario file ({path}): {error}",
+            path = path.display(),
+        )
+    })?;
+
+    let is_json = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.eq_ignore_ascii_case("json"))
+        .unwrap_or_default();
+    if is_json {
+        ::serde_json::from_str(&content).map_err(Into::into)
+    } else {
+        ::serde_yaml::from_str(&content).map_err(Into::into)
+    }
+    .map_err(|error: Error| {
+        anyhow!(
+            "failed to parse scenario file ({path}): {error}",
+            path = path.display(),
+        )
+    })
+}
+
+fn to_arg_value(key: &str, value: Value) -> Result<String> {
+    match value {
+        Value::Bool(value) => Ok(value.to_string()),
+        Value::Number(value) => Ok(value.to_string()),
+        Value::String(value) => Ok(value),
+        Value::Null | Value::Array(_) | Value::Object(_) => {
+            bail!("unsupported value in scenario file: {key}")
+        }
+    }
+}
+
+fn to_snake_case(key: &str) -> String {
+    let mut id = String::with_capacity(key.len() + 4);
+    for c in key.chars() {
+        if c.is_ascii_uppercase() {
+            id.push('_');
+            id.push(c.to_ascii_lowercase());
+        } else if c == '-' {
+            id.push('_');
+        } else {
+            id.push(c);
+        }
+    }
+    id
+}
+
 #[derive(Clone, Default, PartialEq, Parser, Serialize, Deserialize)]
 #[clap(rename_all = "kebab-case")]
 #[serde(rename_all = "camelCase")]
@@ -121,6 +326,149 @@ impl fmt::Debug for CredentialsArgs {
 impl CredentialsArgs {
     #[inline]
     const fn print(&self) {}
+
+    /// Hides the given credentials, e.g. to publish the arguments.
+    pub fn redacted(&self) -> Self {
+        let hide = |value: &Option<String>| value.as_ref().map(|_| "(hidden)".into());
+        Self {
+            access_key: hide(&self.access_key),
+            secret_key: hide(&self.secret_key),
+            security_token: hide(&self.security_token),
+            session_token: hide(&self.session_token),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Parser, Serialize, Deserialize)]
+#[clap(rename_all = "kebab-case")]
+#[serde(rename_all = "camelCase")]
+pub struct DistributedArgs {
+    /// Address of the controller to listen on
+    #[arg(
+        long,
+        env = "SOS_CONTROLLER_ADDRESS",
+        value_name = "ADDR",
+        default_value_t = DistributedArgs::default_controller_address(),
+    )]
+    #[serde(default = "DistributedArgs::default_controller_address")]
+    pub controller_address: SocketAddr,
+
+    /// URL of the controller to connect the worker to
+    #[arg(long, env = "SOS_CONTROLLER_URL", value_name = "URL")]
+    #[serde(default)]
+    pub controller_url: Option<String>,
+
+    #[arg(
+        long,
+        env = "SOS_ROLE",
+        value_enum,
+        default_value_t = Role::default(),
+    )]
+    #[serde(default)]
+    pub role: Role,
+
+    /// File for the controller to write the aggregated summary to as JSON,
+    /// e.g. the termination log of the pod
+    #[arg(long, env = "SOS_SUMMARY_PATH", value_name = "PATH")]
+    #[serde(default)]
+    pub summary_path: Option<PathBuf>,
+
+    /// Time without any report before the controller regards a running worker as lost
+    #[arg(
+        long,
+        env = "SOS_WORKER_TIMEOUT",
+        value_name = "DURATION",
+        default_value_t = DistributedArgs::default_worker_timeout(),
+    )]
+    #[serde(default = "DistributedArgs::default_worker_timeout")]
+    pub worker_timeout: DurationString,
+
+    /// Number of the workers the controller waits for before starting
+    #[arg(
+        long,
+        env = "SOS_WORKERS",
+        value_name = "NUM",
+        default_value_t = DistributedArgs::default_workers(),
+    )]
+    #[serde(default = "DistributedArgs::default_workers")]
+    pub workers: usize,
+}
+
+impl Default for DistributedArgs {
+    fn default() -> Self {
+        Self {
+            controller_address: Self::default_controller_address(),
+            controller_url: None,
+            role: Role::default(),
+            summary_path: None,
+            worker_timeout: Self::default_worker_timeout(),
+            workers: Self::default_workers(),
+        }
+    }
+}
+
+impl DistributedArgs {
+    fn default_controller_address() -> SocketAddr {
+        SocketAddr::from(([0, 0, 0, 0], 9900))
+    }
+
+    fn default_worker_timeout() -> DurationString {
+        Duration::from_secs(30).into()
+    }
+
+    const fn default_workers() -> usize {
+        1
+    }
+
+    fn print(&self) {
+        let Self {
+            controller_address,
+            controller_url,
+            role,
+            summary_path,
+            worker_timeout,
+            workers,
+        } = self;
+
+        info!("controller_address: {controller_address}");
+        info!(
+            "controller_url: {controller_url}",
+            controller_url = controller_url.as_deref().unwrap_or("None"),
+        );
+        info!("role: {role}");
+        info!(
+            "summary_path: {summary_path}",
+            summary_path = summary_path
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_else(|| "None".into(),)
+        );
+        info!("worker_timeout: {worker_timeout}");
+        info!("workers: {workers}");
+    }
+}
+
+/// Role of the process in a distributed run.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "camelCase")]
+pub enum Role {
+    /// Hand the scenario out to the workers and aggregate their metrics
+    Controller,
+    /// Run the scenario alone
+    #[default]
+    Standalone,
+    /// Run the scenario received from the controller
+    Worker,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Controller => f.write_str("controller"),
+            Self::Standalone => f.write_str("standalone"),
+            Self::Worker => f.write_str("worker"),
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Parser, Serialize, Deserialize)]
@@ -131,6 +479,64 @@ pub struct LoadTesterArgs {
     #[serde(default)]
     pub count: Option<Byte>,
 
+    /// Number of hashed directories nested right above the objects
+    #[arg(long, env = "SOS_KEY_DEPTH", value_name = "NUM", default_value_t = 0)]
+    #[serde(default)]
+    pub key_depth: usize,
+
+    /// Distribution of the accessed keys
+    #[arg(
+        long,
+        env = "SOS_KEY_DISTRIBUTION",
+        value_name = "SPEC",
+        default_value_t = KeyDistribution::default(),
+    )]
+    #[serde(default)]
+    pub key_distribution: KeyDistribution,
+
+    /// Offset of the object indices, to split the key space among the workers
+    #[arg(long, env = "SOS_KEY_OFFSET", value_name = "NUM", default_value_t = 0)]
+    #[serde(default)]
+    pub key_offset: usize,
+
+    /// Number of hashed top-level prefixes to spread the objects
+    #[arg(
+        long,
+        env = "SOS_KEY_PREFIXES",
+        value_name = "NUM",
+        default_value_t = 0
+    )]
+    #[serde(default)]
+    pub key_prefixes: usize,
+
+    /// Template of the object keys, with the placeholders: {run_id}, {shard}, {index}, {hash}, {date}
+    ///
+    /// {shard}, or {task} for short, is the index modulo the number of tasks,
+    /// not the task sending the request
+    #[arg(
+        long,
+        env = "SOS_KEY_TEMPLATE",
+        value_name = "TEMPLATE",
+        default_value_t = LoadTesterArgs::default_key_template(),
+    )]
+    #[serde(default = "LoadTesterArgs::default_key_template")]
+    pub key_template: String,
+
+    /// Number of the parts of an object uploaded concurrently
+    #[arg(
+        long,
+        env = "SOS_MULTIPART_CONCURRENCY",
+        value_name = "NUM",
+        default_value_t = LoadTesterArgs::default_multipart_concurrency(),
+    )]
+    #[serde(default = "LoadTesterArgs::default_multipart_concurrency")]
+    pub multipart_concurrency: usize,
+
+    /// Size of each uploaded part, defaulting to the multipart threshold
+    #[arg(long, env = "SOS_MULTIPART_PART_SIZE", value_name = "BYTES")]
+    #[serde(default)]
+    pub multipart_part_size: Option<Byte>,
+
     #[arg(
         long,
         env = "SOS_MULTIPART_THRESHOLD",
@@ -140,27 +546,71 @@ pub struct LoadTesterArgs {
     #[serde(default = "LoadTesterArgs::default_multipart_threshold")]
     pub multipart_threshold: Byte,
 
+    /// Identifier of the run, random by default
+    #[arg(long, env = "SOS_RUN_ID", value_name = "ID")]
+    #[serde(default)]
+    pub run_id: Option<String>,
+
+    /// Seed of the random generators to reproduce the runs
+    #[arg(long, env = "SOS_SEED", value_name = "NUM")]
+    #[serde(default)]
+    pub seed: Option<u64>,
+
     #[arg(long, env = "SOS_SIZE", value_name = "BYTES", default_value_t = LoadTesterArgs::default_size())]
     #[serde(default = "LoadTesterArgs::default_size")]
     pub size: Byte,
 
+    /// Draw the object sizes from the distribution instead of the fixed size
+    #[arg(long, env = "SOS_SIZE_DISTRIBUTION", value_name = "SPEC")]
+    #[serde(default)]
+    pub size_distribution: Option<SizeDistribution>,
+
     #[arg(long, env = "SOS_STEP", value_name = "NUM", default_value_t = LoadTesterArgs::default_step())]
     #[serde(default = "LoadTesterArgs::default_step")]
     pub step: Byte,
+
+    /// Verify the content of the objects on reads
+    #[arg(
+        long,
+        env = "SOS_VERIFY",
+        action = ArgAction::SetTrue,
+        default_value_t = LoadTesterArgs::default_verify(),
+    )]
+    #[serde(default = "LoadTesterArgs::default_verify")]
+    pub verify: bool,
 }
 
 impl Default for LoadTesterArgs {
     fn default() -> Self {
         Self {
             count: None,
+            key_depth: 0,
+            key_distribution: KeyDistribution::default(),
+            key_offset: 0,
+            key_prefixes: 0,
+            key_template: Self::default_key_template(),
+            multipart_concurrency: Self::default_multipart_concurrency(),
+            multipart_part_size: None,
             multipart_threshold: Self::default_multipart_threshold(),
+            run_id: None,
+            seed: None,
             size: Self::default_size(),
+            size_distribution: None,
             step: Self::default_step(),
+            verify: Self::default_verify(),
         }
     }
 }
 
 impl LoadTesterArgs {
+    fn default_key_template() -> String {
+        "/sample/{run_id}/{index}.bin".into()
+    }
+
+    const fn default_multipart_concurrency() -> usize {
+        1
+    }
+
     const fn default_multipart_threshold() -> Byte {
         Byte::from_u64(8_000_000) // 8MB
     }
@@ -173,16 +623,35 @@ impl LoadTesterArgs {
         Byte::from_u64(64)
     }
 
+    const fn default_verify() -> bool {
+        false
+    }
+
     pub const fn minimal_multipart_threshold() -> Byte {
         Byte::from_u64(5_000_000) // 5MB
     }
 
+    pub fn multipart_part_size(&self) -> Byte {
+        self.multipart_part_size.unwrap_or(self.multipart_threshold)
+    }
+
     fn print(&self) {
         let Self {
             count,
+            key_depth,
+            key_distribution,
+            key_offset,
+            key_prefixes,
+            key_template,
+            multipart_concurrency,
+            multipart_part_size,
             multipart_threshold,
+            run_id,
+            seed,
             size,
+            size_distribution,
             step,
+            verify,
         } = self;
 
         info!(
@@ -192,44 +661,473 @@ impl LoadTesterArgs {
                 .map(ToString::to_string)
                 .unwrap_or_else(|| "None".into(),)
         );
+        info!("key_depth: {key_depth}");
+        info!("key_distribution: {key_distribution}");
+        info!("key_offset: {key_offset}");
+        info!("key_prefixes: {key_prefixes}");
+        info!("key_template: {key_template}");
+        info!("multipart_concurrency: {multipart_concurrency}");
+        info!(
+            "multipart_part_size: {multipart_part_size}",
+            multipart_part_size = multipart_part_size
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_else(|| "None".into(),)
+        );
         info!("multipart_threshold: {multipart_threshold}");
+        info!(
+            "run_id: {run_id}",
+            run_id = run_id.as_deref().unwrap_or("None"),
+        );
+        info!(
+            "seed: {seed}",
+            seed = seed
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_else(|| "None".into(),)
+        );
         info!("size: {size}");
+        info!(
+            "size_distribution: {size_distribution}",
+            size_distribution = size_distribution
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_else(|| "None".into(),)
+        );
         info!("step: {step}");
+        info!("verify: {verify}");
+    }
+}
+
+/// Distribution of the accessed keys:
+///
+/// - `sequential`: visit the keys in order
+/// - `uniform`: uniformly random keys
+/// - `zipfian:SKEW`: zipfian random keys, e.g. `zipfian:0.99`
+/// - `hotspot:X:Y`: X% of requests to Y% of keys, e.g. `hotspot:80:20`
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum KeyDistribution {
+    #[default]
+    Sequential,
+    Uniform,
+    Zipfian {
+        skew: f64,
+    },
+    Hotspot {
+        requests: f64,
+        keys: f64,
+    },
+}
+
+impl fmt::Display for KeyDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sequential => f.write_str("sequential"),
+            Self::Uniform => f.write_str("uniform"),
+            Self::Zipfian { skew } => write!(f, "zipfian:{skew}"),
+            Self::Hotspot { requests, keys } => write!(f, "hotspot:{requests}:{keys}"),
+        }
+    }
+}
+
+impl FromStr for KeyDistribution {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (kind, params) = s.split_once(':').unwrap_or((s, ""));
+        let parse_param = |name: &str, value: &str| -> Result<f64> {
+            value
+                .trim()
+                .parse()
+                .map_err(|error| anyhow!("invalid {name} ({s}): {error}"))
+        };
+
+        match kind.to_ascii_lowercase().as_str() {
+            "sequential" => Ok(Self::Sequential),
+            "uniform" => Ok(Self::Uniform),
+            "zipfian" => {
+                let skew = parse_param("skew", params)?;
+                if !(skew > 0.0 && f64::is_finite(skew)) {
+                    bail!("zipfian skew should be positive: {s}")
+                }
+                Ok(Self::Zipfian { skew })
+            }
+            "hotspot" => {
+                let (requests, keys) = params
+                    .split_once(':')
+                    .ok_or_else(|| anyhow!("expected hotspot:X:Y, but given: {s}"))?;
+                let requests = parse_param("requests ratio", requests)?;
+                let keys = parse_param("keys ratio", keys)?;
+                if !(0.0..=100.0).contains(&requests) || !(0.0..=100.0).contains(&keys) {
+                    bail!("hotspot ratios should be between 0 and 100: {s}")
+                }
+                Ok(Self::Hotspot { requests, keys })
+            }
+            _ => bail!("unknown key distribution: {s}"),
+        }
+    }
+}
+
+impl TryFrom<String> for KeyDistribution {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<KeyDistribution> for String {
+    fn from(value: KeyDistribution) -> Self {
+        value.to_string()
+    }
+}
+
+/// Distribution of the object sizes:
+///
+/// - `uniform:MIN..MAX`: uniformly distributed sizes, e.g. `uniform:4KiB..4MiB`
+/// - `lognormal:MEDIAN,SIGMA,MAX`: log-normally distributed sizes, e.g. `lognormal:1MiB,1.5,1GiB`
+/// - `SIZE:WEIGHT,...`: weighted buckets, e.g. `4KiB:60,1MiB:30,128MiB:10`
+/// - `file:PATH`: empirical histogram, a `SIZE,WEIGHT` pair per line
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum SizeDistribution {
+    Buckets(Vec<(Byte, u32)>),
+    Empirical(PathBuf),
+    LogNormal { median: Byte, sigma: f64, max: Byte },
+    Uniform { min: Byte, max: Byte },
+}
+
+impl SizeDistribution {
+    pub fn parse_buckets<'a>(
+        entries: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Vec<(Byte, u32)>> {
+        let buckets = entries
+            .into_iter()
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (size, weight) = entry
+                    .split_once([':', ',', ' ', '\t'])
+                    .ok_or_else(|| anyhow!("expected SIZE:WEIGHT, but given: {entry}"))?;
+                let size = parse_byte(size)?;
+                let weight = weight
+                    .trim()
+                    .parse()
+                    .map_err(|error| anyhow!("invalid weight of {size}: {error}"))?;
+                Ok((size, weight))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if buckets.iter().all(|(_, weight)| *weight == 0) {
+            bail!("at least one size bucket should have a positive weight")
+        }
+        Ok(buckets)
+    }
+}
+
+impl fmt::Display for SizeDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Buckets(buckets) => {
+                for (index, (size, weight)) in buckets.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{size}:{weight}")?;
+                }
+                Ok(())
+            }
+            Self::Empirical(path) => write!(f, "file:{path}", path = path.display()),
+            Self::LogNormal { median, sigma, max } => {
+                write!(f, "lognormal:{median},{sigma},{max}")
+            }
+            Self::Uniform { min, max } => write!(f, "uniform:{min}..{max}"),
+        }
+    }
+}
+
+impl FromStr for SizeDistribution {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(path) = s.strip_prefix("file:") {
+            Ok(Self::Empirical(path.trim().into()))
+        } else if let Some(params) = s.strip_prefix("lognormal:") {
+            let params = params.split(',').map(str::trim).collect::<Vec<_>>();
+            let [median, sigma, max] = params.as_slice() else {
+                bail!("expected lognormal:MEDIAN,SIGMA,MAX, but given: {s}")
+            };
+            let sigma = sigma
+                .parse()
+                .map_err(|error| anyhow!("invalid sigma ({s}): {error}"))?;
+            if !(sigma >= 0.0 && f64::is_finite(sigma)) {
+                bail!("sigma should not be negative: {s}")
+            }
+            Ok(Self::LogNormal {
+                median: parse_byte(median)?,
+                sigma,
+                max: parse_byte(max)?,
+            })
+        } else if let Some(range) = s.strip_prefix("uniform:") {
+            let (min, max) = range
+                .split_once("..")
+                .ok_or_else(|| anyhow!("expected uniform:MIN..MAX, but given: {s}"))?;
+            let (min, max) = (parse_byte(min)?, parse_byte(max)?);
+            if min > max {
+                bail!("the minimum size should not be greater than the maximum: {s}")
+            }
+            Ok(Self::Uniform { min, max })
+        } else {
+            Self::parse_buckets(s.split(',')).map(Self::Buckets)
+        }
+    }
+}
+
+impl TryFrom<String> for SizeDistribution {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<SizeDistribution> for String {
+    fn from(value: SizeDistribution) -> Self {
+        value.to_string()
     }
 }
 
+fn parse_byte(s: &str) -> Result<Byte> {
+    s.trim()
+        .parse()
+        .map_err(|error| anyhow!("invalid size ({s}): {error}"))
+}
+
 #[derive(Clone, Debug, PartialEq, Parser, Serialize, Deserialize)]
 #[clap(rename_all = "kebab-case")]
 #[serde(rename_all = "camelCase")]
 pub struct LoadTesterJobArgs {
-    #[arg(long, env = "SOS_DURATION", value_name = "DURATION")]
-    #[serde(default)]
-    pub duration: Option<DurationString>,
-
     #[arg(
         long,
-        env = "SOS_MODE",
-        value_name = "MODE",
+        env = "SOS_ARRIVAL_OVERFLOW",
+        value_name = "POLICY",
         value_enum,
-        default_value_t = Mode::default(),
+        default_value_t = ArrivalOverflow::default(),
     )]
     #[serde(default)]
-    pub mode: Mode,
+    pub arrival_overflow: ArrivalOverflow,
+
+    #[arg(long, env = "SOS_ARRIVAL_RATE", value_name = "RATE")]
+    #[serde(default)]
+    pub arrival_rate: Option<ArrivalRate>,
+
+    /// Time limit to connect to the storage, on the S3 backend only;
+    /// the reads of the responses are bounded by it plus the first-byte or request timeout
+    #[arg(long, env = "SOS_CONNECT_TIMEOUT", value_name = "DURATION")]
+    #[serde(default)]
+    pub connect_timeout: Option<DurationString>,
 
+    /// Number of objects deleted together with a `DeleteObjects` request in the delete mode
     #[arg(
         long,
-        env = "SOS_NO_PROGRESS_BAR",
-        action = ArgAction::SetTrue,
-        default_value_t = LoadTesterJobArgs::default_no_progress_bar(),
+        env = "SOS_DELETE_BATCH",
+        value_name = "NUM",
+        default_value_t = LoadTesterJobArgs::default_delete_batch(),
     )]
-    #[serde(default = "LoadTesterJobArgs::default_no_progress_bar")]
-    pub no_progress_bar: bool,
+    #[serde(default = "LoadTesterJobArgs::default_delete_batch")]
+    pub delete_batch: usize,
 
+    /// Rewrite the deleted objects to keep the population steady in the delete mode
     #[arg(
         long,
-        env = "SOS_THREADS_MAX",
-        value_name = "NUM",
-        default_value_t = LoadTesterJobArgs::default_threads_max(),
+        env = "SOS_DELETE_REFILL",
+        action = ArgAction::SetTrue,
+        default_value_t = LoadTesterJobArgs::default_delete_refill(),
+    )]
+    #[serde(default = "LoadTesterJobArgs::default_delete_refill")]
+    pub delete_refill: bool,
+
+    #[arg(long, env = "SOS_DURATION", value_name = "DURATION")]
+    #[serde(default)]
+    pub duration: Option<DurationString>,
+
+    /// Keep going on the failed requests unless more than the percentage of them fail
+    #[arg(long, env = "SOS_ERROR_BUDGET", value_name = "PERCENT")]
+    #[serde(default)]
+    pub error_budget: Option<f64>,
+
+    /// Time limit to receive the first byte of each read
+    #[arg(long, env = "SOS_FIRST_BYTE_TIMEOUT", value_name = "DURATION")]
+    #[serde(default)]
+    pub first_byte_timeout: Option<DurationString>,
+
+    /// Delimiter of the listings in the list mode, listing recursively if not given
+    #[arg(long, env = "SOS_LIST_DELIMITER", value_name = "DELIMITER")]
+    #[serde(default)]
+    pub list_delimiter: Option<String>,
+
+    /// Number of the leading directories of the keys to be listed in the list mode
+    #[arg(
+        long,
+        env = "SOS_LIST_DEPTH",
+        value_name = "NUM",
+        default_value_t = LoadTesterJobArgs::default_list_depth(),
+    )]
+    #[serde(default = "LoadTesterJobArgs::default_list_depth")]
+    pub list_depth: usize,
+
+    /// Maximum number of keys of each listed page in the list mode
+    #[arg(
+        long,
+        env = "SOS_LIST_PAGE_SIZE",
+        value_name = "NUM",
+        default_value_t = LoadTesterJobArgs::default_list_page_size(),
+    )]
+    #[serde(default = "LoadTesterJobArgs::default_list_page_size")]
+    pub list_page_size: usize,
+
+    #[arg(
+        long,
+        env = "SOS_MAX_OUTSTANDING",
+        value_name = "NUM",
+        default_value_t = LoadTesterJobArgs::default_max_outstanding(),
+    )]
+    #[serde(default = "LoadTesterJobArgs::default_max_outstanding")]
+    pub max_outstanding: usize,
+
+    #[arg(
+        long,
+        env = "SOS_MIX",
+        value_name = "RATIOS",
+        default_value_t = OperationMix::default(),
+    )]
+    #[serde(default)]
+    pub mix: OperationMix,
+
+    #[arg(
+        long,
+        env = "SOS_MODE",
+        value_name = "MODE",
+        value_enum,
+        default_value_t = Mode::default(),
+    )]
+    #[serde(default)]
+    pub mode: Mode,
+
+    /// Keep the objects in the bucket after the run
+    #[arg(
+        long,
+        env = "SOS_NO_CLEANUP",
+        action = ArgAction::SetTrue,
+        default_value_t = LoadTesterJobArgs::default_no_cleanup(),
+    )]
+    #[serde(default = "LoadTesterJobArgs::default_no_cleanup")]
+    pub no_cleanup: bool,
+
+    #[arg(
+        long,
+        env = "SOS_NO_PROGRESS_BAR",
+        action = ArgAction::SetTrue,
+        default_value_t = LoadTesterJobArgs::default_no_progress_bar(),
+    )]
+    #[serde(default = "LoadTesterJobArgs::default_no_progress_bar")]
+    pub no_progress_bar: bool,
+
+    /// Offsets of the ranged reads
+    #[arg(
+        long,
+        env = "SOS_RANGE_OFFSET",
+        value_name = "POLICY",
+        value_enum,
+        default_value_t = RangeOffset::default(),
+    )]
+    #[serde(default)]
+    pub range_offset: RangeOffset,
+
+    /// Read the byte ranges of the objects instead of the whole objects
+    #[arg(long, env = "SOS_RANGE_SIZE", value_name = "BYTES")]
+    #[serde(default)]
+    pub range_size: Option<Byte>,
+
+    /// Number of the ranged reads on each object
+    #[arg(
+        long,
+        env = "SOS_RANGES_PER_OBJECT",
+        value_name = "NUM",
+        default_value_t = LoadTesterJobArgs::default_ranges_per_object(),
+    )]
+    #[serde(default = "LoadTesterJobArgs::default_ranges_per_object")]
+    pub ranges_per_object: usize,
+
+    /// Time limit of each attempt of the requests
+    #[arg(long, env = "SOS_REQUEST_TIMEOUT", value_name = "DURATION")]
+    #[serde(default)]
+    pub request_timeout: Option<DurationString>,
+
+    /// Base delay before retrying a failed request, doubled on each attempt with a full jitter
+    #[arg(
+        long,
+        env = "SOS_RETRY_BACKOFF",
+        value_name = "DURATION",
+        default_value_t = LoadTesterJobArgs::default_retry_backoff(),
+    )]
+    #[serde(default = "LoadTesterJobArgs::default_retry_backoff")]
+    pub retry_backoff: DurationString,
+
+    /// Upper bound of the delay before retrying a failed request
+    #[arg(
+        long,
+        env = "SOS_RETRY_BACKOFF_MAX",
+        value_name = "DURATION",
+        default_value_t = LoadTesterJobArgs::default_retry_backoff_max(),
+    )]
+    #[serde(default = "LoadTesterJobArgs::default_retry_backoff_max")]
+    pub retry_backoff_max: DurationString,
+
+    /// Maximum number of attempts of each request, including the first one
+    #[arg(
+        long,
+        env = "SOS_RETRY_MAX_ATTEMPTS",
+        value_name = "NUM",
+        default_value_t = LoadTesterJobArgs::default_retry_max_attempts(),
+    )]
+    #[serde(default = "LoadTesterJobArgs::default_retry_max_attempts")]
+    pub retry_max_attempts: usize,
+
+    /// Classes of the errors to be retried
+    #[arg(
+        long,
+        env = "SOS_RETRY_ON",
+        value_name = "CLASS",
+        value_enum,
+        value_delimiter = ',',
+        default_values_t = LoadTesterJobArgs::default_retry_on(),
+    )]
+    #[serde(default = "LoadTesterJobArgs::default_retry_on")]
+    pub retry_on: Vec<RetryClass>,
+
+    #[arg(
+        long,
+        env = "SOS_STAGES",
+        value_name = "DURATION:TARGET",
+        value_delimiter = ','
+    )]
+    #[serde(default)]
+    pub stages: Vec<Stage>,
+
+    /// Warn the requests in flight for longer than the duration
+    #[arg(long, env = "SOS_STUCK_REQUEST_THRESHOLD", value_name = "DURATION")]
+    #[serde(default)]
+    pub stuck_request_threshold: Option<DurationString>,
+
+    #[arg(
+        long,
+        env = "SOS_THREADS_MAX",
+        value_name = "NUM",
+        default_value_t = LoadTesterJobArgs::default_threads_max(),
     )]
     #[serde(default = "LoadTesterJobArgs::default_threads_max")]
     pub threads_max: usize,
@@ -238,31 +1136,161 @@ pub struct LoadTesterJobArgs {
 impl Default for LoadTesterJobArgs {
     fn default() -> Self {
         Self {
+            arrival_overflow: ArrivalOverflow::default(),
+            arrival_rate: None,
+            connect_timeout: None,
+            delete_batch: Self::default_delete_batch(),
+            delete_refill: Self::default_delete_refill(),
             duration: None,
+            error_budget: None,
+            first_byte_timeout: None,
+            list_delimiter: None,
+            list_depth: Self::default_list_depth(),
+            list_page_size: Self::default_list_page_size(),
+            max_outstanding: Self::default_max_outstanding(),
+            mix: OperationMix::default(),
             mode: Mode::default(),
+            no_cleanup: Self::default_no_cleanup(),
             no_progress_bar: Self::default_no_progress_bar(),
+            range_offset: RangeOffset::default(),
+            range_size: None,
+            ranges_per_object: Self::default_ranges_per_object(),
+            request_timeout: None,
+            retry_backoff: Self::default_retry_backoff(),
+            retry_backoff_max: Self::default_retry_backoff_max(),
+            retry_max_attempts: Self::default_retry_max_attempts(),
+            retry_on: Self::default_retry_on(),
+            stages: Vec::default(),
+            stuck_request_threshold: None,
             threads_max: Self::default_threads_max(),
         }
     }
 }
 
 impl LoadTesterJobArgs {
+    const fn default_delete_batch() -> usize {
+        1
+    }
+
+    const fn default_delete_refill() -> bool {
+        false
+    }
+
+    const fn default_list_depth() -> usize {
+        1
+    }
+
+    const fn default_list_page_size() -> usize {
+        1000
+    }
+
+    const fn default_max_outstanding() -> usize {
+        1024
+    }
+
+    const fn default_no_cleanup() -> bool {
+        false
+    }
+
     const fn default_no_progress_bar() -> bool {
         false
     }
 
+    const fn default_ranges_per_object() -> usize {
+        1
+    }
+
+    fn default_retry_backoff() -> DurationString {
+        Duration::from_millis(100).into()
+    }
+
+    fn default_retry_backoff_max() -> DurationString {
+        Duration::from_secs(10).into()
+    }
+
+    const fn default_retry_max_attempts() -> usize {
+        1
+    }
+
+    fn default_retry_on() -> Vec<RetryClass> {
+        vec![RetryClass::Network, RetryClass::Server, RetryClass::Timeout]
+    }
+
     const fn default_threads_max() -> usize {
         8
     }
 
+    /// Returns the number of tasks to spawn, enough to reach every concurrency target.
+    pub fn total_tasks(&self) -> usize {
+        self.stages
+            .iter()
+            .filter_map(|stage| match stage.target {
+                StageTarget::Concurrency(concurrency) => Some(concurrency),
+                StageTarget::Rate(_) => None,
+            })
+            .fold(self.threads_max, usize::max)
+    }
+
+    /// Returns the total duration of the run,
+    /// defaulting to the sum of all stages if not given.
+    pub fn total_duration(&self) -> Option<Duration> {
+        self.duration.map(Into::into).or_else(|| {
+            if self.stages.is_empty() {
+                None
+            } else {
+                Some(self.stages.iter().map(|stage| stage.duration()).sum())
+            }
+        })
+    }
+
     fn print(&self) {
         let Self {
+            arrival_overflow,
+            arrival_rate,
+            connect_timeout,
+            delete_batch,
+            delete_refill,
             duration,
+            error_budget,
+            first_byte_timeout,
+            list_delimiter,
+            list_depth,
+            list_page_size,
+            max_outstanding,
+            mix,
             mode,
+            no_cleanup,
             no_progress_bar,
+            range_offset,
+            range_size,
+            ranges_per_object,
+            request_timeout,
+            retry_backoff,
+            retry_backoff_max,
+            retry_max_attempts,
+            retry_on,
+            stages,
+            stuck_request_threshold,
             threads_max,
         } = self;
 
+        info!("arrival_overflow: {arrival_overflow}");
+        info!(
+            "arrival_rate: {arrival_rate}",
+            arrival_rate = arrival_rate
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_else(|| "None".into(),)
+        );
+        info!(
+            "connect_timeout: {connect_timeout}",
+            connect_timeout = connect_timeout
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_else(|| "None".into(),)
+        );
+        info!("delete_batch: {delete_batch}");
+        info!("delete_refill: {delete_refill}");
         info!(
             "duration: {duration}",
             duration = duration
@@ -270,12 +1298,328 @@ impl LoadTesterJobArgs {
                 .map(ToString::to_string)
                 .unwrap_or_else(|| "None".into(),)
         );
+        info!(
+            "error_budget: {error_budget}",
+            error_budget = error_budget
+                .as_ref()
+                .map(|percent| format!("{percent}%"))
+                .unwrap_or_else(|| "None".into(),)
+        );
+        info!(
+            "first_byte_timeout: {first_byte_timeout}",
+            first_byte_timeout = first_byte_timeout
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_else(|| "None".into(),)
+        );
+        info!(
+            "list_delimiter: {list_delimiter}",
+            list_delimiter = list_delimiter.as_deref().unwrap_or("None"),
+        );
+        info!("list_depth: {list_depth}");
+        info!("list_page_size: {list_page_size}");
+        info!("max_outstanding: {max_outstanding}");
+        info!("mix: {mix}");
         info!("mode: {mode:?}");
+        info!("no_cleanup: {no_cleanup}");
         info!("no_progress_bar: {no_progress_bar}");
+        info!("range_offset: {range_offset}");
+        info!(
+            "range_size: {range_size}",
+            range_size = range_size
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_else(|| "None".into(),)
+        );
+        info!("ranges_per_object: {ranges_per_object}");
+        info!(
+            "request_timeout: {request_timeout}",
+            request_timeout = request_timeout
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_else(|| "None".into(),)
+        );
+        info!("retry_backoff: {retry_backoff}");
+        info!("retry_backoff_max: {retry_backoff_max}");
+        info!("retry_max_attempts: {retry_max_attempts}");
+        info!(
+            "retry_on: {retry_on}",
+            retry_on = retry_on
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+        info!(
+            "stages: {stages}",
+            stages = stages
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+        info!(
+            "stuck_request_threshold: {stuck_request_threshold}",
+            stuck_request_threshold = stuck_request_threshold
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_else(|| "None".into(),)
+        );
         info!("threads_max: {threads_max}");
     }
 }
 
+/// Offsets of the ranged reads.
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    ValueEnum,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum RangeOffset {
+    /// Uniformly random offsets
+    #[default]
+    Random,
+    /// Continue from the end of the previous range of the same object
+    Sequential,
+}
+
+impl fmt::Display for RangeOffset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Random => f.write_str("random"),
+            Self::Sequential => f.write_str("sequential"),
+        }
+    }
+}
+
+/// Classes of the failed requests to be retried.
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ValueEnum,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum RetryClass {
+    /// 4xx responses, e.g. to ride out the throttling
+    Client,
+    /// Connection failures
+    Network,
+    /// 5xx responses
+    Server,
+    /// Timed-out requests
+    Timeout,
+}
+
+impl fmt::Display for RetryClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Client => f.write_str("client"),
+            Self::Network => f.write_str("network"),
+            Self::Server => f.write_str("server"),
+            Self::Timeout => f.write_str("timeout"),
+        }
+    }
+}
+
+/// Policy on arrivals of the open-loop mode when too many requests are outstanding.
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    ValueEnum,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum ArrivalOverflow {
+    /// Wait for a free slot, still measuring latency from the intended time
+    #[default]
+    Delay,
+    /// Skip the arrival
+    Drop,
+}
+
+impl fmt::Display for ArrivalOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Delay => f.write_str("delay"),
+            Self::Drop => f.write_str("drop"),
+        }
+    }
+}
+
+/// Target rate of the open-loop mode, e.g. `1000/s` (requests) or `1GB/s` (bytes).
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ArrivalRate {
+    Bytes(Byte),
+    Requests(f64),
+}
+
+impl ArrivalRate {
+    pub fn requests_per_sec(&self, mean_size: f64) -> f64 {
+        match self {
+            Self::Bytes(bytes) => bytes.as_u64() as f64 / mean_size.max(1.0),
+            Self::Requests(requests) => *requests,
+        }
+    }
+}
+
+impl fmt::Display for ArrivalRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bytes(bytes) => write!(f, "{bytes}B/s", bytes = bytes.as_u64()),
+            Self::Requests(requests) => write!(f, "{requests}/s"),
+        }
+    }
+}
+
+impl FromStr for ArrivalRate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rate = s
+            .trim()
+            .strip_suffix("/s")
+            .ok_or_else(|| anyhow!("expected RATE/s, but given: {s}"))?
+            .trim();
+
+        let rate = match rate.parse() {
+            Ok(requests) => Self::Requests(requests),
+            Err(_) => Self::Bytes(
+                rate.parse()
+                    .map_err(|error| anyhow!("invalid arrival rate ({s}): {error}"))?,
+            ),
+        };
+        match rate {
+            Self::Requests(requests) if !(requests > 0.0 && requests.is_finite()) => {
+                bail!("arrival rate should be positive: {s}")
+            }
+            Self::Bytes(bytes) if bytes.as_u64() == 0 => {
+                bail!("arrival rate should be positive: {s}")
+            }
+            rate => Ok(rate),
+        }
+    }
+}
+
+impl TryFrom<String> for ArrivalRate {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<ArrivalRate> for String {
+    fn from(value: ArrivalRate) -> Self {
+        value.to_string()
+    }
+}
+
+/// A stage of the load profile, e.g. `30s:8` (8 concurrent tasks) or `1m:500/s` (500 ops/s).
+///
+/// The load ramps linearly from the target of the previous stage if both have the same kind.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Stage {
+    pub duration: DurationString,
+    pub target: StageTarget,
+}
+
+impl Stage {
+    pub fn duration(&self) -> Duration {
+        self.duration.into()
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { duration, target } = self;
+        write!(f, "{duration}:{target}")
+    }
+}
+
+impl FromStr for Stage {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (duration, target) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("expected DURATION:TARGET, but given: {s}"))?;
+        Ok(Self {
+            duration: duration
+                .trim()
+                .parse()
+                .map_err(|error| anyhow!("invalid stage duration ({s}): {error}"))?,
+            target: target.parse()?,
+        })
+    }
+}
+
+impl TryFrom<String> for Stage {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Stage> for String {
+    fn from(value: Stage) -> Self {
+        value.to_string()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StageTarget {
+    Concurrency(usize),
+    /// Requests per second
+    Rate(f64),
+}
+
+impl fmt::Display for StageTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Concurrency(concurrency) => write!(f, "{concurrency}"),
+            Self::Rate(rate) => write!(f, "{rate}/s"),
+        }
+    }
+}
+
+impl FromStr for StageTarget {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.strip_suffix("/s") {
+            Some(rate) => match rate.trim().parse() {
+                Ok(rate) if rate >= 0.0 => Ok(Self::Rate(rate)),
+                Ok(_) => bail!("stage rate should not be negative: {s}"),
+                Err(error) => bail!("invalid stage rate ({s}): {error}"),
+            },
+            None => s
+                .parse()
+                .map(Self::Concurrency)
+                .map_err(|error| anyhow!("invalid stage concurrency ({s}): {error}")),
+        }
+    }
+}
+
 #[derive(
     Copy,
     Clone,
@@ -290,12 +1634,454 @@ impl LoadTesterJobArgs {
     Deserialize,
     ValueEnum,
 )]
+#[serde(rename_all = "camelCase")]
 pub enum Mode {
+    Delete,
+    Head,
+    List,
+    Mixed,
+    // Keep the names of the older scenarios
+    #[serde(alias = "Read")]
+    #[value(alias = "Read")]
     Read,
     #[default]
+    #[serde(alias = "Write")]
+    #[value(alias = "Write")]
     Write,
 }
 
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.to_possible_value() {
+            Some(value) => f.write_str(value.get_name()),
+            None => write!(f, "{self:?}"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "operator", derive(::schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub enum Operation {
+    Get,
+    Put,
+    Head,
+    Delete,
+    List,
+}
+
+impl Operation {
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Get => "get",
+            Self::Put => "put",
+            Self::Head => "head",
+            Self::Delete => "delete",
+            Self::List => "list",
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Operation {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "get" => Ok(Self::Get),
+            "put" => Ok(Self::Put),
+            "head" => Ok(Self::Head),
+            "delete" => Ok(Self::Delete),
+            "list" => Ok(Self::List),
+            s => bail!("unknown operation: {s}"),
+        }
+    }
+}
+
+/// Relative weights of each operation in the mixed mode,
+/// e.g. `get=70,put=20,head=5,delete=5`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct OperationMix {
+    pub get: u32,
+    pub put: u32,
+    pub head: u32,
+    pub delete: u32,
+}
+
+impl Default for OperationMix {
+    fn default() -> Self {
+        Self {
+            get: 70,
+            put: 20,
+            head: 5,
+            delete: 5,
+        }
+    }
+}
+
+impl OperationMix {
+    pub const fn weights(&self) -> [(Operation, u32); 4] {
+        let Self {
+            get,
+            put,
+            head,
+            delete,
+        } = *self;
+
+        [
+            (Operation::Get, get),
+            (Operation::Put, put),
+            (Operation::Head, head),
+            (Operation::Delete, delete),
+        ]
+    }
+}
+
+impl fmt::Display for OperationMix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, (operation, weight)) in self.weights().into_iter().enumerate() {
+            if index > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{operation}={weight}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for OperationMix {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mix = Self {
+            get: 0,
+            put: 0,
+            head: 0,
+            delete: 0,
+        };
+
+        for entry in s
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (operation, weight) = entry
+                .split_once(['=', ':'])
+                .ok_or_else(|| anyhow!("expected OP=WEIGHT, but given: {entry}"))?;
+            let weight = weight
+                .trim()
+                .parse()
+                .map_err(|error| anyhow!("invalid weight of {operation}: {error}"))?;
+            match operation.parse()? {
+                Operation::Get => mix.get = weight,
+                Operation::Put => mix.put = weight,
+                Operation::Head => mix.head = weight,
+                Operation::Delete => mix.delete = weight,
+                Operation::List => bail!("listing is not supported in the mixed mode: {entry}"),
+            }
+        }
+
+        if mix.weights().iter().all(|(_, weight)| *weight == 0) {
+            bail!("at least one operation should have a positive weight: {s}")
+        }
+        Ok(mix)
+    }
+}
+
+impl TryFrom<String> for OperationMix {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<OperationMix> for String {
+    fn from(value: OperationMix) -> Self {
+        value.to_string()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Parser, Serialize, Deserialize)]
+#[clap(rename_all = "kebab-case")]
+#[serde(rename_all = "camelCase")]
+pub struct MetricsArgs {
+    #[arg(long, env = "SOS_METRICS_ADDRESS", value_name = "ADDR")]
+    #[serde(default)]
+    pub metrics_address: Option<SocketAddr>,
+
+    /// File to write the final report to at exit, as CSV if it ends with `.csv` or JSON otherwise
+    #[arg(long, env = "SOS_REPORT", value_name = "PATH")]
+    #[serde(default)]
+    pub report: Option<PathBuf>,
+
+    /// Service level objectives to assert at the end of the run,
+    /// e.g. `p99(get) < 50ms`; the process exits with 2 if any fails
+    #[arg(long, env = "SOS_SLO", value_name = "OBJECTIVE", value_delimiter = ',')]
+    #[serde(default)]
+    pub slo: Vec<Slo>,
+
+    /// File to append the metrics of each interval to during the run,
+    /// as CSV if it ends with `.csv` or JSON Lines otherwise
+    #[arg(long, env = "SOS_TIMESERIES", value_name = "PATH")]
+    #[serde(default)]
+    pub timeseries: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env = "SOS_TIMESERIES_INTERVAL",
+        value_name = "DURATION",
+        default_value_t = MetricsArgs::default_timeseries_interval(),
+    )]
+    #[serde(default = "MetricsArgs::default_timeseries_interval")]
+    pub timeseries_interval: DurationString,
+}
+
+impl Default for MetricsArgs {
+    fn default() -> Self {
+        Self {
+            metrics_address: None,
+            report: None,
+            slo: Vec::default(),
+            timeseries: None,
+            timeseries_interval: Self::default_timeseries_interval(),
+        }
+    }
+}
+
+impl MetricsArgs {
+    fn default_timeseries_interval() -> DurationString {
+        Duration::from_secs(1).into()
+    }
+
+    fn print(&self) {
+        let Self {
+            metrics_address,
+            report,
+            slo,
+            timeseries,
+            timeseries_interval,
+        } = self;
+
+        info!(
+            "metrics_address: {metrics_address}",
+            metrics_address = metrics_address
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_else(|| "None".into(),)
+        );
+        info!(
+            "report: {report}",
+            report = report
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_else(|| "None".into(),)
+        );
+        info!(
+            "slo: {slo}",
+            slo = slo
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", "),
+        );
+        info!(
+            "timeseries: {timeseries}",
+            timeseries = timeseries
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_else(|| "None".into(),)
+        );
+        info!("timeseries_interval: {timeseries_interval}");
+    }
+}
+
+/// Service level objective of the run, e.g. `p99(get) < 50ms`,
+/// `throughput(put) > 2GB/s`, `rate > 1000/s` or `error_rate < 0.1%`.
+///
+/// It asserts the totals of all operations if no operation is given.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Slo {
+    pub comparison: Comparison,
+    pub objective: SloObjective,
+    pub operation: Option<Operation>,
+}
+
+impl fmt::Display for Slo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            comparison,
+            objective,
+            operation,
+        } = self;
+
+        match objective {
+            SloObjective::ErrorRate(_) => f.write_str("error_rate")?,
+            SloObjective::Latency { percentile, .. } => write!(f, "p{percentile}")?,
+            SloObjective::Rate(_) => f.write_str("rate")?,
+            SloObjective::Throughput(_) => f.write_str("throughput")?,
+        }
+        if let Some(operation) = operation {
+            write!(f, "({operation})")?;
+        }
+        write!(f, " {comparison} ")?;
+        match objective {
+            SloObjective::ErrorRate(percent) => write!(f, "{percent}%"),
+            SloObjective::Latency { latency, .. } => write!(f, "{latency}"),
+            SloObjective::Rate(rate) => write!(f, "{rate}/s"),
+            SloObjective::Throughput(bytes) => write!(f, "{bytes}B/s", bytes = bytes.as_u64()),
+        }
+    }
+}
+
+impl FromStr for Slo {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let index = s
+            .find(['<', '>'])
+            .ok_or_else(|| anyhow!("expected METRIC(OPERATION) < VALUE, but given: {s}"))?;
+        let (metric, rest) = s.split_at(index);
+        let (comparison, value) = match rest.strip_prefix(['<', '>']).unwrap() {
+            value if value.starts_with('=') => rest.split_at(2),
+            _ => rest.split_at(1),
+        };
+        let comparison = comparison.parse()?;
+
+        let metric = metric.trim();
+        let (name, operation) = match metric.split_once('(') {
+            Some((name, operation)) => {
+                let operation = operation
+                    .strip_suffix(')')
+                    .ok_or_else(|| anyhow!("unclosed operation of the SLO: {s}"))?;
+                (name.trim(), Some(operation.parse()?))
+            }
+            None => (metric, None),
+        };
+
+        let value = value.trim();
+        let objective = match name {
+            "error_rate" => SloObjective::ErrorRate(
+                value
+                    .strip_suffix('%')
+                    .ok_or_else(|| anyhow!("expected the error rate in percent, but given: {s}"))?
+                    .trim()
+                    .parse()
+                    .map_err(|error| anyhow!("invalid error rate ({s}): {error}"))?,
+            ),
+            "rate" => SloObjective::Rate(
+                value
+                    .strip_suffix("/s")
+                    .ok_or_else(|| anyhow!("expected RATE/s, but given: {s}"))?
+                    .trim()
+                    .parse()
+                    .map_err(|error| anyhow!("invalid rate ({s}): {error}"))?,
+            ),
+            "throughput" => SloObjective::Throughput(parse_byte(
+                value
+                    .strip_suffix("/s")
+                    .ok_or_else(|| anyhow!("expected BYTES/s, but given: {s}"))?,
+            )?),
+            name => match name.strip_prefix('p').map(str::parse) {
+                Some(Ok(percentile)) if percentile > 0.0 && percentile <= 100.0 => {
+                    SloObjective::Latency {
+                        latency: value
+                            .parse()
+                            .map_err(|error| anyhow!("invalid latency ({s}): {error}"))?,
+                        percentile,
+                    }
+                }
+                _ => bail!("unknown metric of the SLO: {name}"),
+            },
+        };
+
+        Ok(Self {
+            comparison,
+            objective,
+            operation,
+        })
+    }
+}
+
+impl TryFrom<String> for Slo {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Slo> for String {
+    fn from(value: Slo) -> Self {
+        value.to_string()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SloObjective {
+    /// Percentage of the finally failed requests
+    ErrorRate(f64),
+    Latency {
+        latency: DurationString,
+        percentile: f64,
+    },
+    /// Succeeded requests per second
+    Rate(f64),
+    /// Bytes of the succeeded requests per second
+    Throughput(Byte),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+impl Comparison {
+    pub fn test(&self, actual: f64, threshold: f64) -> bool {
+        match self {
+            Self::Greater => actual > threshold,
+            Self::GreaterOrEqual => actual >= threshold,
+            Self::Less => actual < threshold,
+            Self::LessOrEqual => actual <= threshold,
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Greater => f.write_str(">"),
+            Self::GreaterOrEqual => f.write_str(">="),
+            Self::Less => f.write_str("<"),
+            Self::LessOrEqual => f.write_str("<="),
+        }
+    }
+}
+
+impl FromStr for Comparison {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            ">" => Ok(Self::Greater),
+            ">=" => Ok(Self::GreaterOrEqual),
+            "<" => Ok(Self::Less),
+            "<=" => Ok(Self::LessOrEqual),
+            s => bail!("unknown comparison: {s}"),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Parser, Serialize, Deserialize)]
 #[clap(rename_all = "kebab-case")]
 #[serde(rename_all = "camelCase")]
@@ -351,3 +2137,204 @@ impl From<RegionArgs> for Region {
         Self::Custom { endpoint, region }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn scenario(name: &str, content: &str) -> PathBuf {
+        let path = ::std::env::temp_dir().join(format!(
+            "sos-scenario-{pid}-{name}",
+            pid = ::std::process::id(),
+        ));
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn parse(path: &Path, args: &[&str]) -> Result<Args> {
+        parse_with(Args::command(), path, args)
+    }
+
+    fn parse_with(command: Command, path: &Path, args: &[&str]) -> Result<Args> {
+        let config = path.display().to_string();
+        let base = ["sos", "--bucket-name", "sos-test", "--config", &config];
+        Args::try_parse_with_config_command(command, base.iter().chain(args))
+    }
+
+    #[test]
+    fn config_precedence() {
+        let path = scenario(
+            "precedence.yaml",
+            "keyDepth: 1\nkeyOffset: 2\nkeyPrefixes: 3\nmode: Read\n",
+        );
+        // The env is shared by the tests running in parallel, so read the variables no other sets;
+        // clap reads them on declaring
+        ::std::env::set_var("SOS_TEST_PRECEDENCE_KEY_DEPTH", "10");
+        ::std::env::set_var("SOS_TEST_PRECEDENCE_KEY_OFFSET", "20");
+        let command = Args::command()
+            .mut_arg("key_depth", |arg| arg.env("SOS_TEST_PRECEDENCE_KEY_DEPTH"))
+            .mut_arg("key_offset", |arg| {
+                arg.env("SOS_TEST_PRECEDENCE_KEY_OFFSET")
+            });
+        ::std::env::remove_var("SOS_TEST_PRECEDENCE_KEY_DEPTH");
+        ::std::env::remove_var("SOS_TEST_PRECEDENCE_KEY_OFFSET");
+        let args = parse_with(command, &path, &["--key-depth", "100"]);
+        fs::remove_file(path).unwrap();
+
+        // CLI > env > file
+        let args = args.unwrap();
+        assert_eq!(args.load_tester.key_depth, 100);
+        assert_eq!(args.load_tester.key_offset, 20);
+        assert_eq!(args.load_tester.key_prefixes, 3);
+        assert_eq!(args.load_tester_job.mode, Mode::Read);
+    }
+
+    #[test]
+    fn config_arrays() {
+        let path = scenario(
+            "arrays.json",
+            r#"{"slo": ["p99(get) < 50ms", "error_rate < 1%"], "stages": ["1s:10", "2s:0"]}"#,
+        );
+        let args = parse(&path, &[]);
+        fs::remove_file(path).unwrap();
+
+        let args = args.unwrap();
+        assert_eq!(args.metrics.slo.len(), 2);
+        assert_eq!(args.metrics.slo[1].to_string(), "error_rate < 1%");
+        assert_eq!(args.load_tester_job.stages.len(), 2);
+    }
+
+    #[test]
+    fn config_unknown_fields() {
+        for (name, content) in [
+            ("unknown.yaml", "keyDepth: 1\nkeyDeep: 2\n"),
+            ("config.yaml", "config: other.yaml\n"),
+            ("object.yaml", "keyDepth:\n  value: 1\n"),
+        ] {
+            let path = scenario(name, content);
+            let result = parse(&path, &[]);
+            fs::remove_file(path).unwrap();
+            assert!(result.is_err(), "{content}");
+        }
+    }
+
+    #[test]
+    fn mode_old_names() {
+        assert_eq!(
+            ::serde_json::from_str::<Mode>(r#""Read""#).unwrap(),
+            Mode::Read
+        );
+        assert_eq!(
+            ::serde_json::from_str::<Mode>(r#""write""#).unwrap(),
+            Mode::Write
+        );
+        assert_eq!(Mode::from_str("Read", false).unwrap(), Mode::Read);
+        assert_eq!(Mode::Read.to_string(), "read");
+    }
+
+    fn roundtrip<T>(s: &str) -> T
+    where
+        T: FromStr<Err = Error> + fmt::Display + fmt::Debug + PartialEq,
+    {
+        let parsed: T = s.parse().unwrap();
+        assert_eq!(parsed.to_string().parse::<T>().unwrap(), parsed, "{s}");
+        parsed
+    }
+
+    #[test]
+    fn parse_operation_mix() {
+        let mix: OperationMix = roundtrip("get=50, put:30,delete=20");
+        assert_eq!(mix.weights().map(|(_, weight)| weight), [50, 30, 0, 20]);
+
+        for mix in ["get", "get=x", "list=10", "get=0,put=0", "copy=1"] {
+            assert!(mix.parse::<OperationMix>().is_err(), "{mix}");
+        }
+    }
+
+    #[test]
+    fn parse_size_distribution() {
+        assert_eq!(
+            roundtrip::<SizeDistribution>("uniform:4KiB..4MiB"),
+            SizeDistribution::Uniform {
+                min: Byte::from_u64(4096),
+                max: Byte::from_u64(4 << 20),
+            },
+        );
+        roundtrip::<SizeDistribution>("lognormal:1MiB,1.5,1GiB");
+        assert_eq!(
+            roundtrip::<SizeDistribution>("4KiB:60,1MiB:30"),
+            SizeDistribution::Buckets(vec![
+                (Byte::from_u64(4096), 60),
+                (Byte::from_u64(1 << 20), 30),
+            ]),
+        );
+        assert_eq!(
+            roundtrip::<SizeDistribution>("file:sizes.csv"),
+            SizeDistribution::Empirical("sizes.csv".into()),
+        );
+
+        for distribution in [
+            "uniform:4MiB..4KiB",
+            "uniform:4KiB",
+            "lognormal:1MiB,-1,1GiB",
+            "lognormal:1MiB,1.5",
+            "4KiB:0",
+            "4KiB",
+        ] {
+            assert!(
+                distribution.parse::<SizeDistribution>().is_err(),
+                "{distribution}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_key_distribution() {
+        assert_eq!(
+            roundtrip::<KeyDistribution>("Uniform"),
+            KeyDistribution::Uniform
+        );
+        assert_eq!(
+            roundtrip::<KeyDistribution>("zipfian:0.99"),
+            KeyDistribution::Zipfian { skew: 0.99 },
+        );
+        assert_eq!(
+            roundtrip::<KeyDistribution>("hotspot:80:20"),
+            KeyDistribution::Hotspot {
+                requests: 80.0,
+                keys: 20.0,
+            },
+        );
+
+        for distribution in [
+            "zipfian:0",
+            "zipfian",
+            "hotspot:80",
+            "hotspot:120:20",
+            "random",
+        ] {
+            assert!(
+                distribution.parse::<KeyDistribution>().is_err(),
+                "{distribution}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_arrival_rate() {
+        assert_eq!(
+            roundtrip::<ArrivalRate>("1000/s"),
+            ArrivalRate::Requests(1000.0)
+        );
+        assert_eq!(
+            roundtrip::<ArrivalRate>("1GB/s"),
+            ArrivalRate::Bytes(Byte::from_u64(1_000_000_000)),
+        );
+
+        for rate in ["1000", "0/s", "-1/s", "0B/s", "fast/s"] {
+            assert!(rate.parse::<ArrivalRate>().is_err(), "{rate}");
+        }
+    }
+}
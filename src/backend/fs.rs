@@ -0,0 +1,253 @@
+use std::{
+    ffi::OsString,
+    io::{self, SeekFrom},
+    ops::Range,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWrite},
+};
+
+use super::{clamp_range, normalize_key, Backend, BackendError, ListPage, UploadedPart};
+
+/// Stores the objects as files under the root directory.
+pub struct FsBackend {
+    root: PathBuf,
+}
+
+impl FsBackend {
+    /// Directory of the parts of the incomplete multipart uploads
+    const MULTIPART_DIR: &'static str = ".sos-multipart";
+
+    /// Suffix of the files being written, to be renamed into place once complete
+    const TEMP_SUFFIX: &'static str = ".sos-tmp";
+
+    pub async fn try_new(root: PathBuf, create: bool) -> Result<Self> {
+        if !fs::try_exists(&root).await? {
+            if create {
+                fs::create_dir_all(&root).await?;
+            } else {
+                bail!("no such bucket: {}", root.display())
+            }
+        }
+        Ok(Self { root })
+    }
+
+    /// Returns the path of the key, which should stay under the root.
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        let key = normalize_key(key);
+        if Path::new(key)
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            bail!("invalid key: {key}")
+        }
+        Ok(self.root.join(key))
+    }
+
+    fn upload_path(&self, upload_id: &str) -> Result<PathBuf> {
+        if upload_id.contains(['/', '\\']) || upload_id.starts_with('.') {
+            bail!(BackendError::NoSuchUpload(upload_id.into()))
+        }
+        Ok(self.root.join(Self::MULTIPART_DIR).join(upload_id))
+    }
+
+    /// Writes the file at once, so that the readers never see it partially written.
+    async fn write_file(path: &Path, data: &[u8]) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        // Rename within the same directory to keep it atomic
+        let mut temp = path.file_name().map(OsString::from).unwrap_or_default();
+        temp.push(format!(
+            ".{random:016x}{suffix}",
+            random = ::rand::random::<u64>(),
+            suffix = Self::TEMP_SUFFIX,
+        ));
+        let temp = path.with_file_name(temp);
+        if let Err(error) = fs::write(&temp, data).await {
+            fs::remove_file(&temp).await.ok();
+            return Err(error.into());
+        }
+        if let Err(error) = fs::rename(&temp, path).await {
+            fs::remove_file(&temp).await.ok();
+            return Err(error.into());
+        }
+        Ok(())
+    }
+
+    /// Returns the sorted keys in the directory of the prefix.
+    ///
+    /// The subdirectories are returned as the keys ending with `/`, unless recursive.
+    async fn walk(&self, prefix: &str, recursive: bool) -> Result<Vec<String>> {
+        let dir = match prefix.rfind('/') {
+            Some(end) => self.path(&prefix[..end])?,
+            None => self.root.clone(),
+        };
+
+        let mut keys = vec![];
+        let mut dirs = vec![dir];
+        while let Some(dir) = dirs.pop() {
+            let mut entries = match fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+                Err(error) => return Err(error.into()),
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                let Some(key) = path
+                    .strip_prefix(&self.root)
+                    .ok()
+                    .and_then(Path::to_str)
+                    .filter(|key| key.starts_with(prefix) || prefix.starts_with(*key))
+                else {
+                    continue;
+                };
+
+                if entry.file_type().await?.is_dir() {
+                    if key == Self::MULTIPART_DIR {
+                        continue;
+                    }
+                    if recursive {
+                        dirs.push(path);
+                    } else if is_occupied(&path).await? {
+                        // The common prefix of the objects in it, like S3 does
+                        keys.push(format!("{key}/"));
+                    }
+                } else if !key.ends_with(Self::TEMP_SUFFIX) {
+                    keys.push(key.into());
+                }
+            }
+        }
+        keys.sort_unstable();
+        Ok(keys)
+    }
+}
+
+#[async_trait]
+impl Backend for FsBackend {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        Self::write_file(&self.path(key)?, data).await
+    }
+
+    async fn get(
+        &self,
+        key: &str,
+        range: Option<Range<u64>>,
+        writer: &mut (dyn AsyncWrite + Send + Unpin),
+    ) -> Result<()> {
+        let mut file = fs::File::open(self.path(key)?)
+            .await
+            .map_err(|error| map_not_found(error, key))?;
+        let range = clamp_range(range, file.metadata().await?.len());
+        file.seek(SeekFrom::Start(range.start)).await?;
+        tokio::io::copy(&mut file.take(range.end - range.start), writer).await?;
+        Ok(())
+    }
+
+    async fn head(&self, key: &str) -> Result<u64> {
+        let metadata = fs::metadata(self.path(key)?)
+            .await
+            .map_err(|error| map_not_found(error, key))?;
+        Ok(metadata.len())
+    }
+
+    async fn list(
+        &self,
+        prefix: &str,
+        delimiter: Option<&str>,
+        token: Option<String>,
+        max_keys: usize,
+    ) -> Result<ListPage> {
+        // Read the directories below only when not grouped by them
+        let prefix = normalize_key(prefix);
+        let keys = self.walk(prefix, delimiter != Some("/")).await?;
+        Ok(ListPage::paginate(
+            keys,
+            prefix,
+            delimiter,
+            token.as_deref(),
+            max_keys,
+        ))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match fs::remove_file(self.path(key)?).await {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn create_multipart(&self, _key: &str) -> Result<String> {
+        // Unique across the processes sharing the directory, e.g. the distributed workers
+        let upload_id = format!(
+            "{pid}-{random:016x}",
+            pid = ::std::process::id(),
+            random = ::rand::random::<u64>(),
+        );
+        fs::create_dir_all(self.upload_path(&upload_id)?).await?;
+        Ok(upload_id)
+    }
+
+    async fn upload_part(
+        &self,
+        _key: &str,
+        upload_id: &str,
+        part_number: u32,
+        data: &[u8],
+    ) -> Result<UploadedPart> {
+        let path = self.upload_path(upload_id)?;
+        if !fs::try_exists(&path).await? {
+            bail!(BackendError::NoSuchUpload(upload_id.into()))
+        }
+        fs::write(path.join(part_number.to_string()), data).await?;
+        Ok(UploadedPart {
+            part_number,
+            etag: format!("{upload_id}-{part_number}"),
+        })
+    }
+
+    async fn complete_multipart(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: Vec<UploadedPart>,
+    ) -> Result<()> {
+        let path = self.upload_path(upload_id)?;
+        let mut data = vec![];
+        for UploadedPart { part_number, .. } in parts {
+            let part = fs::read(path.join(part_number.to_string()))
+                .await
+                .map_err(|_| BackendError::NoSuchUpload(format!("{upload_id}/{part_number}")))?;
+            data.extend_from_slice(&part);
+        }
+        Self::write_file(&self.path(key)?, &data).await?;
+        fs::remove_dir_all(path).await?;
+        Ok(())
+    }
+
+    async fn abort_multipart(&self, _key: &str, upload_id: &str) -> Result<()> {
+        match fs::remove_dir_all(self.upload_path(upload_id)?).await {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn map_not_found(error: io::Error, key: &str) -> anyhow::Error {
+    if error.kind() == io::ErrorKind::NotFound {
+        BackendError::NoSuchKey(normalize_key(key).into()).into()
+    } else {
+        error.into()
+    }
+}
+
+/// Returns whether the directory has any entry, not to list the emptied ones.
+async fn is_occupied(dir: &Path) -> Result<bool> {
+    Ok(fs::read_dir(dir).await?.next_entry().await?.is_some())
+}
@@ -0,0 +1,157 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Range,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use super::{clamp_range, normalize_key, Backend, BackendError, ListPage, UploadedPart};
+
+/// Keeps the objects in the process memory, to test the load generator itself.
+#[derive(Default)]
+pub struct MemoryBackend {
+    objects: Mutex<BTreeMap<String, Arc<[u8]>>>,
+    uploads: Mutex<HashMap<String, BTreeMap<u32, Vec<u8>>>>,
+    upload_counter: AtomicU64,
+}
+
+impl MemoryBackend {
+    /// Number of the multipart uploads neither completed nor aborted.
+    pub fn uploads(&self) -> usize {
+        self.uploads.lock().unwrap().len()
+    }
+
+    fn object(&self, key: &str) -> Result<Arc<[u8]>> {
+        let key = normalize_key(key);
+        self.objects
+            .lock()
+            .unwrap()
+            .get(key)
+            .cloned()
+            .ok_or_else(|| BackendError::NoSuchKey(key.into()).into())
+    }
+}
+
+#[async_trait]
+impl Backend for MemoryBackend {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        self.objects
+            .lock()
+            .unwrap()
+            .insert(normalize_key(key).into(), data.into());
+        Ok(())
+    }
+
+    async fn get(
+        &self,
+        key: &str,
+        range: Option<Range<u64>>,
+        writer: &mut (dyn AsyncWrite + Send + Unpin),
+    ) -> Result<()> {
+        let object = self.object(key)?;
+        let range = clamp_range(range, object.len() as u64);
+        writer
+            .write_all(&object[range.start as usize..range.end as usize])
+            .await?;
+        Ok(())
+    }
+
+    async fn head(&self, key: &str) -> Result<u64> {
+        self.object(key).map(|object| object.len() as u64)
+    }
+
+    async fn list(
+        &self,
+        prefix: &str,
+        delimiter: Option<&str>,
+        token: Option<String>,
+        max_keys: usize,
+    ) -> Result<ListPage> {
+        let prefix = normalize_key(prefix);
+        let keys = self
+            .objects
+            .lock()
+            .unwrap()
+            .range(prefix.to_string()..)
+            .map(|(key, _)| key.clone())
+            .take_while(|key| key.starts_with(prefix))
+            .collect::<Vec<_>>();
+        Ok(ListPage::paginate(
+            keys,
+            prefix,
+            delimiter,
+            token.as_deref(),
+            max_keys,
+        ))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.objects.lock().unwrap().remove(normalize_key(key));
+        Ok(())
+    }
+
+    async fn create_multipart(&self, _key: &str) -> Result<String> {
+        let upload_id = self
+            .upload_counter
+            .fetch_add(1, Ordering::SeqCst)
+            .to_string();
+        self.uploads
+            .lock()
+            .unwrap()
+            .insert(upload_id.clone(), BTreeMap::default());
+        Ok(upload_id)
+    }
+
+    async fn upload_part(
+        &self,
+        _key: &str,
+        upload_id: &str,
+        part_number: u32,
+        data: &[u8],
+    ) -> Result<UploadedPart> {
+        self.uploads
+            .lock()
+            .unwrap()
+            .get_mut(upload_id)
+            .ok_or_else(|| BackendError::NoSuchUpload(upload_id.into()))?
+            .insert(part_number, data.to_vec());
+        Ok(UploadedPart {
+            part_number,
+            etag: format!("{upload_id}-{part_number}"),
+        })
+    }
+
+    async fn complete_multipart(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: Vec<UploadedPart>,
+    ) -> Result<()> {
+        let mut uploaded = self
+            .uploads
+            .lock()
+            .unwrap()
+            .remove(upload_id)
+            .ok_or_else(|| BackendError::NoSuchUpload(upload_id.into()))?;
+
+        let mut data = vec![];
+        for UploadedPart { part_number, .. } in parts {
+            let part = uploaded
+                .remove(&part_number)
+                .ok_or_else(|| BackendError::NoSuchUpload(format!("{upload_id}/{part_number}")))?;
+            data.extend_from_slice(&part);
+        }
+        self.put(key, &data).await
+    }
+
+    async fn abort_multipart(&self, _key: &str, upload_id: &str) -> Result<()> {
+        self.uploads.lock().unwrap().remove(upload_id);
+        Ok(())
+    }
+}
@@ -0,0 +1,303 @@
+mod fs;
+mod memory;
+mod s3;
+mod sigv4;
+
+use std::{error::Error, fmt, ops::Range, sync::Arc, time::Duration};
+
+use ::s3::error::S3Error;
+use anyhow::Result;
+use async_trait::async_trait;
+use tokio::io::AsyncWrite;
+
+use crate::args::{Args, BackendKind};
+
+pub use self::{fs::FsBackend, memory::MemoryBackend, s3::S3Backend, sigv4::Signer};
+
+/// Storage under the load, addressed by object keys.
+///
+/// The leading slashes of the keys are ignored.
+#[async_trait]
+pub trait Backend: Send + Sync {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<()>;
+
+    /// Streams the object, or the byte range of it, into the writer.
+    async fn get(
+        &self,
+        key: &str,
+        range: Option<Range<u64>>,
+        writer: &mut (dyn AsyncWrite + Send + Unpin),
+    ) -> Result<()>;
+
+    /// Returns the size of the object.
+    async fn head(&self, key: &str) -> Result<u64>;
+
+    async fn list(
+        &self,
+        prefix: &str,
+        delimiter: Option<&str>,
+        token: Option<String>,
+        max_keys: usize,
+    ) -> Result<ListPage>;
+
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    /// Deletes the objects together, one by one unless the backend supports the batches.
+    async fn delete_many(&self, keys: &[String]) -> Result<()> {
+        for key in keys {
+            self.delete(key).await?;
+        }
+        Ok(())
+    }
+
+    /// Starts a multipart upload and returns its ID.
+    async fn create_multipart(&self, key: &str) -> Result<String>;
+
+    async fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: u32,
+        data: &[u8],
+    ) -> Result<UploadedPart>;
+
+    async fn complete_multipart(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: Vec<UploadedPart>,
+    ) -> Result<()>;
+
+    async fn abort_multipart(&self, key: &str, upload_id: &str) -> Result<()>;
+}
+
+pub async fn try_new(args: &Args) -> Result<Arc<dyn Backend>> {
+    let Args {
+        backend,
+        bucket_name,
+        bucket_create,
+        credentials,
+        load_tester_job,
+        region,
+        ..
+    } = args;
+    // rust-s3 bounds each read of the responses with its request timeout as well,
+    // so leave the slow responses to the first-byte and request timeouts
+    let request_timeout = load_tester_job.connect_timeout.map(|connect_timeout| {
+        let first_byte = load_tester_job.first_byte_timeout.map(Duration::from);
+        let total = load_tester_job.request_timeout.map(Duration::from);
+        Duration::from(connect_timeout) + first_byte.max(total).unwrap_or_default()
+    });
+
+    Ok(match backend {
+        BackendKind::Fs(path) => {
+            Arc::new(FsBackend::try_new(path.join(bucket_name), *bucket_create).await?)
+        }
+        BackendKind::Memory => Arc::new(MemoryBackend::default()),
+        BackendKind::S3 => Arc::new(
+            S3Backend::try_new(
+                bucket_name,
+                *bucket_create,
+                request_timeout,
+                credentials,
+                region,
+            )
+            .await?,
+        ),
+    })
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ListPage {
+    pub keys: Vec<String>,
+    /// Common prefixes grouped by the delimiter
+    pub prefixes: Vec<String>,
+    /// Token to continue the listing, if truncated
+    pub next_token: Option<String>,
+}
+
+impl ListPage {
+    /// Paginates the sorted keys like S3 does, using the last listed entry as the token.
+    fn paginate(
+        keys: impl IntoIterator<Item = String>,
+        prefix: &str,
+        delimiter: Option<&str>,
+        token: Option<&str>,
+        max_keys: usize,
+    ) -> Self {
+        let is_listed = |key: &str| match token {
+            Some(token) => {
+                key <= token
+                    || (delimiter.is_some_and(|delimiter| token.ends_with(delimiter))
+                        && key.starts_with(token))
+            }
+            None => false,
+        };
+
+        let mut page = Self::default();
+        let mut last = None;
+        for key in keys {
+            if !key.starts_with(prefix) || is_listed(&key) {
+                continue;
+            }
+
+            let common_prefix = delimiter.and_then(|delimiter| {
+                key[prefix.len()..]
+                    .find(delimiter)
+                    .map(|at| key[..prefix.len() + at + delimiter.len()].to_string())
+            });
+            if common_prefix.is_some() && page.prefixes.last() == common_prefix.as_ref() {
+                continue;
+            }
+            if page.keys.len() + page.prefixes.len() >= max_keys {
+                page.next_token = last;
+                break;
+            }
+
+            match common_prefix {
+                Some(common_prefix) => {
+                    last = Some(common_prefix.clone());
+                    page.prefixes.push(common_prefix);
+                }
+                None => {
+                    last = Some(key.clone());
+                    page.keys.push(key);
+                }
+            }
+        }
+        page
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UploadedPart {
+    pub part_number: u32,
+    pub etag: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BackendError {
+    NoSuchKey(String),
+    NoSuchUpload(String),
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoSuchKey(key) => write!(f, "no such key: {key}"),
+            Self::NoSuchUpload(upload_id) => write!(f, "no such upload: {upload_id}"),
+        }
+    }
+}
+
+impl Error for BackendError {}
+
+/// Returns whether the range to read starts beyond the end of the object.
+pub fn is_range_not_satisfiable(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<S3Error>(),
+        Some(S3Error::HttpFailWithBody(416, _))
+    )
+}
+
+fn normalize_key(key: &str) -> &str {
+    key.trim_start_matches('/')
+}
+
+/// Clamps the byte range into the object.
+fn clamp_range(range: Option<Range<u64>>, len: u64) -> Range<u64> {
+    match range {
+        Some(range) => range.start.min(len)..range.end.min(len),
+        None => 0..len,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys() -> Vec<String> {
+        ["a/1", "a/2", "b/1", "c"].map(Into::into).to_vec()
+    }
+
+    #[test]
+    fn paginate_keys() {
+        let page = ListPage::paginate(keys(), "", None, None, 3);
+        assert_eq!(page.keys, ["a/1", "a/2", "b/1"]);
+        assert_eq!(page.next_token.as_deref(), Some("b/1"));
+
+        let page = ListPage::paginate(keys(), "", None, page.next_token.as_deref(), 3);
+        assert_eq!(page.keys, ["c"]);
+        assert_eq!(page.next_token, None);
+    }
+
+    #[test]
+    fn paginate_common_prefixes() {
+        let page = ListPage::paginate(keys(), "", Some("/"), None, 1);
+        assert_eq!(page.prefixes, ["a/"]);
+        assert_eq!(page.next_token.as_deref(), Some("a/"));
+
+        let page = ListPage::paginate(keys(), "", Some("/"), page.next_token.as_deref(), 2);
+        assert_eq!(page.prefixes, ["b/"]);
+        assert_eq!(page.keys, ["c"]);
+        assert_eq!(page.next_token, None);
+    }
+
+    #[tokio::test]
+    async fn memory_multipart_roundtrip() {
+        let backend = MemoryBackend::default();
+        let upload_id = backend.create_multipart("/a").await.unwrap();
+        let second = backend
+            .upload_part("/a", &upload_id, 2, b"world")
+            .await
+            .unwrap();
+        let first = backend
+            .upload_part("/a", &upload_id, 1, b"hello ")
+            .await
+            .unwrap();
+        backend
+            .complete_multipart("/a", &upload_id, vec![first, second])
+            .await
+            .unwrap();
+
+        let mut buf = vec![];
+        backend.get("/a", Some(6..11), &mut buf).await.unwrap();
+        assert_eq!(buf, b"world");
+        assert_eq!(backend.head("a").await.unwrap(), 11);
+
+        backend.delete("/a").await.unwrap();
+        assert!(backend.head("/a").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn fs_list_prefix_directory() {
+        let root = std::env::temp_dir().join(format!("sos-fs-{}", std::process::id()));
+        let backend = FsBackend::try_new(root.clone(), true).await.unwrap();
+        for key in keys() {
+            backend.put(&key, b"data").await.unwrap();
+        }
+        assert!(backend.put("a/../../escaped", b"data").await.is_err());
+
+        let page = backend.list("a/", None, None, 10).await.unwrap();
+        assert_eq!(page.keys, ["a/1", "a/2"]);
+
+        backend.delete("b/1").await.unwrap();
+        let page = backend.list("", Some("/"), None, 10).await.unwrap();
+        assert_eq!(page.prefixes, ["a/"]);
+        assert_eq!(page.keys, ["c"]);
+
+        let upload_id = backend.create_multipart("d").await.unwrap();
+        let part = backend
+            .upload_part("d", &upload_id, 1, b"data")
+            .await
+            .unwrap();
+        backend
+            .complete_multipart("d", &upload_id, vec![part])
+            .await
+            .unwrap();
+        let page = backend.list("", None, None, 10).await.unwrap();
+        assert_eq!(page.keys, ["a/1", "a/2", "c", "d"]);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
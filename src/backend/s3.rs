@@ -0,0 +1,428 @@
+use std::{fmt::Write, ops::Range, time::Duration};
+
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use reqwest::{Client, Method};
+use s3::{
+    error::S3Error,
+    serde_types::{InitiateMultipartUploadResponse, Part},
+    Bucket, BucketConfiguration,
+};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWrite;
+use tracing::{instrument, Level};
+
+use crate::args::{CredentialsArgs, RegionArgs};
+
+use super::{
+    normalize_key,
+    sigv4::{uri_encode, Signer},
+    Backend, ListPage, UploadedPart,
+};
+
+pub struct S3Backend {
+    bucket: Bucket,
+    /// Client of the requests rust-s3 does not support
+    client: Client,
+    signer: Signer,
+}
+
+impl S3Backend {
+    const CONTENT_TYPE: &'static str = "application/octet-stream";
+
+    /// Maximum number of the keys in a `DeleteObjects` request
+    const DELETE_MAX_KEYS: usize = 1000;
+
+    pub async fn try_new(
+        bucket_name: &str,
+        bucket_create: bool,
+        request_timeout: Option<Duration>,
+        credentials: &CredentialsArgs,
+        region: &RegionArgs,
+    ) -> Result<Self> {
+        let mut bucket = Bucket::new(
+            bucket_name,
+            region.clone().into(),
+            credentials.clone().into(),
+        )
+        .map_err(|error| anyhow!("failed to initialize object storage bucket client: {error}"))?
+        .with_path_style();
+        if request_timeout.is_some() {
+            bucket.set_request_timeout(request_timeout);
+        }
+
+        if !check_bucket_exists(&bucket).await {
+            if bucket_create {
+                let config = BucketConfiguration::private();
+                let response = Bucket::create_with_path_style(
+                    bucket_name,
+                    region.clone().into(),
+                    credentials.clone().into(),
+                    config,
+                )
+                .await
+                .map_err(|error| anyhow!("failed to create object storage bucket: {error}"))?;
+                if response.success() {
+                    bucket = response.bucket.with_path_style();
+                    if request_timeout.is_some() {
+                        bucket.set_request_timeout(request_timeout);
+                    }
+                } else {
+                    bail!("failed to create bucket: {bucket_name}")
+                }
+            } else {
+                bail!("no such bucket: {bucket_name}")
+            }
+        }
+
+        // Bound the connects and the reads like rust-s3 does
+        let mut client = Client::builder();
+        if let Some(request_timeout) = request_timeout {
+            client = client
+                .connect_timeout(request_timeout)
+                .read_timeout(request_timeout);
+        }
+        Ok(Self {
+            bucket,
+            client: client
+                .build()
+                .map_err(|error| anyhow!("failed to initialize object storage client: {error}"))?,
+            signer: Signer::new(credentials, &region.region),
+        })
+    }
+
+    /// Sends a signed request on the bucket, failing on the error responses like rust-s3 does.
+    ///
+    /// The endpoint is resolved by rust-s3, defaulting to HTTPS without a scheme.
+    async fn send(
+        &self,
+        method: Method,
+        query: &[(&str, &str)],
+        mut headers: Vec<(&str, String)>,
+        body: Vec<u8>,
+    ) -> Result<reqwest::Response> {
+        let path = format!("/{name}", name = self.bucket.name());
+        let mut url = self.bucket.url();
+        for (index, (name, value)) in query.iter().enumerate() {
+            let separator = if index == 0 { '?' } else { '&' };
+            write!(
+                url,
+                "{separator}{name}={value}",
+                name = uri_encode(name, true),
+                value = uri_encode(value, true),
+            )?;
+        }
+
+        headers.push(("host", self.bucket.host()));
+        let signed = self.signer.sign(
+            method.as_str(),
+            &path,
+            query,
+            &headers,
+            Signer::UNSIGNED_PAYLOAD,
+            Utc::now(),
+        );
+
+        let mut request = self.client.request(method, url).body(body);
+        for (name, value) in headers.into_iter().chain(signed) {
+            request = request.header(name, value);
+        }
+        let response = request.send().await?;
+
+        let status = response.status();
+        if status.is_success() {
+            Ok(response)
+        } else {
+            let body = response.text().await.unwrap_or_default();
+            bail!(S3Error::HttpFailWithBody(status.as_u16(), body))
+        }
+    }
+}
+
+#[async_trait]
+impl Backend for S3Backend {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        self.bucket.put_object(key, data).await?;
+        Ok(())
+    }
+
+    async fn get(
+        &self,
+        key: &str,
+        range: Option<Range<u64>>,
+        writer: &mut (dyn AsyncWrite + Send + Unpin),
+    ) -> Result<()> {
+        match range {
+            // The end of the range is inclusive
+            Some(range) => {
+                self.bucket
+                    .get_object_range_to_writer(key, range.start, Some(range.end - 1), writer)
+                    .await?
+            }
+            None => self.bucket.get_object_to_writer(key, writer).await?,
+        };
+        Ok(())
+    }
+
+    async fn head(&self, key: &str) -> Result<u64> {
+        let (head, _) = self.bucket.head_object(key).await?;
+        Ok(head.content_length.unwrap_or_default().try_into()?)
+    }
+
+    async fn list(
+        &self,
+        prefix: &str,
+        delimiter: Option<&str>,
+        token: Option<String>,
+        max_keys: usize,
+    ) -> Result<ListPage> {
+        let (page, _) = self
+            .bucket
+            .list_page(
+                prefix.into(),
+                delimiter.map(Into::into),
+                token,
+                None,
+                Some(max_keys),
+            )
+            .await?;
+
+        Ok(ListPage {
+            keys: page.contents.into_iter().map(|object| object.key).collect(),
+            prefixes: page
+                .common_prefixes
+                .unwrap_or_default()
+                .into_iter()
+                .map(|prefix| prefix.prefix)
+                .collect(),
+            next_token: page.next_continuation_token.filter(|_| page.is_truncated),
+        })
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.bucket.delete_object(key).await?;
+        Ok(())
+    }
+
+    async fn delete_many(&self, keys: &[String]) -> Result<()> {
+        for keys in keys.chunks(Self::DELETE_MAX_KEYS) {
+            let body = ::quick_xml::se::to_string(&Delete {
+                object: keys
+                    .iter()
+                    .map(|key| ObjectIdentifier {
+                        key: normalize_key(key),
+                    })
+                    .collect(),
+                quiet: true,
+            })?;
+
+            let headers = vec![
+                ("content-md5", STANDARD.encode(md5::compute(&body).0)),
+                ("content-type", "application/xml".into()),
+            ];
+            let response = self
+                .send(Method::POST, &[("delete", "")], headers, body.into_bytes())
+                .await
+                .map_err(|error| anyhow!("failed to delete objects: {error}"))?;
+
+            // The quiet response lists the keys failed to delete only
+            let status = response.status().as_u16();
+            let body = response.text().await?;
+            match ::quick_xml::de::from_str(&body)
+                .map_err(|error| anyhow!("invalid response of deleting objects: {error}"))?
+            {
+                DeleteResponse::DeleteResult(DeleteResult { error }) => {
+                    if let Some(ErrorResponse { code, key, message }) = error.first() {
+                        bail!(
+                            "failed to delete {len} objects, including {key}: {code}: {message}",
+                            len = error.len(),
+                        )
+                    }
+                }
+                // The request may fail after the response has started
+                DeleteResponse::Error(ErrorResponse { code, message, .. }) => {
+                    bail!(S3Error::HttpFailWithBody(
+                        status,
+                        format!("{code}: {message}")
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn create_multipart(&self, key: &str) -> Result<String> {
+        let InitiateMultipartUploadResponse { upload_id, .. } = self
+            .bucket
+            .initiate_multipart_upload(key, Self::CONTENT_TYPE)
+            .await?;
+        Ok(upload_id)
+    }
+
+    async fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: u32,
+        mut data: &[u8],
+    ) -> Result<UploadedPart> {
+        let Part { part_number, etag } = self
+            .bucket
+            .put_multipart_stream(&mut data, key, part_number, upload_id, Self::CONTENT_TYPE)
+            .await?;
+        Ok(UploadedPart { part_number, etag })
+    }
+
+    async fn complete_multipart(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: Vec<UploadedPart>,
+    ) -> Result<()> {
+        let parts = parts
+            .into_iter()
+            .map(|UploadedPart { part_number, etag }| Part { part_number, etag })
+            .collect();
+        self.bucket
+            .complete_multipart_upload(key, upload_id, parts)
+            .await?;
+        Ok(())
+    }
+
+    async fn abort_multipart(&self, key: &str, upload_id: &str) -> Result<()> {
+        self.bucket.abort_upload(key, upload_id).await?;
+        Ok(())
+    }
+}
+
+async fn check_bucket_exists(bucket: &Bucket) -> bool {
+    try_check_bucket_exists(bucket).await.is_ok()
+}
+
+#[instrument(skip_all, err(level = Level::ERROR))]
+async fn try_check_bucket_exists(bucket: &Bucket) -> Result<()> {
+    const TEST_FILE: &str = "/_sos_bucket_test";
+
+    bucket.put_object(TEST_FILE, TEST_FILE.as_bytes()).await?;
+    bucket.delete_object(TEST_FILE).await.ok();
+    Ok(())
+}
+
+/// Request of `DeleteObjects`
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Delete<'a> {
+    object: Vec<ObjectIdentifier<'a>>,
+    quiet: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct ObjectIdentifier<'a> {
+    key: &'a str,
+}
+
+/// Response of `DeleteObjects`, named after its root element
+#[derive(Debug, Deserialize)]
+enum DeleteResponse {
+    DeleteResult(DeleteResult),
+    Error(ErrorResponse),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DeleteResult {
+    #[serde(default)]
+    error: Vec<ErrorResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ErrorResponse {
+    code: String,
+    #[serde(default)]
+    key: String,
+    #[serde(default)]
+    message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bucket(endpoint: &str) -> Bucket {
+        let region = RegionArgs {
+            endpoint: endpoint.into(),
+            region: "us-east-1".into(),
+        };
+        let credentials = CredentialsArgs {
+            access_key: None,
+            secret_key: None,
+            security_token: None,
+            session_token: None,
+        };
+        Bucket::new("sos-test", region.into(), credentials.into())
+            .unwrap()
+            .with_path_style()
+    }
+
+    #[test]
+    fn resolve_endpoint() {
+        let bucket = self::bucket("s3.amazonaws.com");
+        assert_eq!(bucket.url(), "https://s3.amazonaws.com/sos-test");
+        assert_eq!(bucket.host(), "s3.amazonaws.com");
+
+        let bucket = self::bucket("minio:9000");
+        assert_eq!(bucket.url(), "https://minio:9000/sos-test");
+        assert_eq!(bucket.host(), "minio:9000");
+
+        let bucket = self::bucket("http://127.0.0.1:9000");
+        assert_eq!(bucket.url(), "http://127.0.0.1:9000/sos-test");
+        assert_eq!(bucket.host(), "127.0.0.1:9000");
+    }
+
+    #[test]
+    fn write_delete_request() {
+        let body = ::quick_xml::se::to_string(&Delete {
+            object: vec![
+                ObjectIdentifier { key: "a/1&2" },
+                ObjectIdentifier { key: "a/3" },
+            ],
+            quiet: true,
+        })
+        .unwrap();
+        assert_eq!(
+            body,
+            "<Delete><Object><Key>a/1&amp;2</Key></Object><Object><Key>a/3</Key></Object><Quiet>true</Quiet></Delete>",
+        );
+    }
+
+    #[test]
+    fn read_delete_response() {
+        let response = ::quick_xml::de::from_str(
+            r#"<?xml version="1.0" encoding="UTF-8"?><DeleteResult><Error><Key>a/1&amp;2</Key><Code>AccessDenied</Code><Message>Access Denied</Message></Error></DeleteResult>"#,
+        )
+        .unwrap();
+        let DeleteResponse::DeleteResult(DeleteResult { error }) = response else {
+            panic!("unexpected response: {response:?}")
+        };
+        assert_eq!(error.len(), 1);
+        assert_eq!(error[0].key, "a/1&2");
+        assert_eq!(error[0].code, "AccessDenied");
+
+        let response = ::quick_xml::de::from_str("<DeleteResult></DeleteResult>").unwrap();
+        assert!(
+            matches!(response, DeleteResponse::DeleteResult(DeleteResult { ref error }) if error.is_empty())
+        );
+
+        let response = ::quick_xml::de::from_str(
+            "<Error><Code>InternalError</Code><Message>We encountered an internal error.</Message></Error>",
+        )
+        .unwrap();
+        assert!(
+            matches!(response, DeleteResponse::Error(ErrorResponse { ref code, .. }) if code == "InternalError")
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use ark_core::signal::FunctionSignal;
+use clap::Parser;
+use stressful_object_storage::operator::Operator;
+use tokio::runtime::Runtime;
+use tracing::{error, info};
+
+#[derive(Parser)]
+#[clap(rename_all = "kebab-case")]
+struct Args {
+    /// Print the definition of the custom resource in YAML and exit
+    #[arg(long)]
+    print_crd: bool,
+}
+
+fn main() {
+    let rt = Runtime::new().expect("failed to create a tokio runtime");
+    rt.block_on(main_async())
+}
+
+async fn main_async() {
+    ::ark_core::tracer::init_once();
+
+    let Args { print_crd } = Args::parse();
+    if print_crd {
+        match Operator::crd() {
+            Ok(crd) => print!("{crd}"),
+            Err(error) => error!("{error}"),
+        }
+        return;
+    }
+    info!("Welcome to stressful object storage operator!");
+
+    let signal = FunctionSignal::default().trap_on_panic();
+    if let Err(error) = signal.trap_on_sigint() {
+        error!("{error}");
+        return;
+    }
+
+    info!("Booting...");
+    let operator = match Operator::try_default().await {
+        Ok(operator) => operator,
+        Err(error) => signal.panic(error).await,
+    };
+
+    info!("Creating operator tasks...");
+    let handler = operator.spawn(signal.clone());
+
+    info!("Ready");
+    signal.wait_to_terminate().await;
+
+    info!("Terminating...");
+    if let Err(error) = handler.await {
+        error!("{error}");
+    }
+
+    signal.exit().await
+}
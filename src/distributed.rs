@@ -0,0 +1,553 @@
+use std::{
+    pin::pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use ark_core::signal::FunctionSignal;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs,
+    net::TcpListener,
+    select, spawn,
+    sync::watch,
+    task::JoinHandle,
+    time::{sleep, Instant},
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    args::{Args, DistributedArgs},
+    backend,
+    key::KeyTemplate,
+    metrics::MetricsSnapshot,
+    report::Reporter,
+    session::{self, ObjectStorageSession},
+    slo::{self, SloViolation},
+};
+
+/// Scenario handed out to a worker on registration.
+///
+/// It includes the credentials, so keep the controller in a trusted network.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Assignment {
+    pub args: Args,
+    pub id: usize,
+    pub workers: usize,
+}
+
+/// Metrics of a worker, reported periodically and once finished.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkerReport {
+    /// Error of the failed worker
+    pub error: Option<String>,
+    pub finished: bool,
+    /// Whether the worker has initialized, waiting for the others
+    #[serde(default)]
+    pub ready: bool,
+    pub snapshot: MetricsSnapshot,
+    /// Time the workload has started at, after the initialization
+    #[serde(default)]
+    pub started_at: Option<DateTime<Utc>>,
+}
+
+/// Hands the scenario out to the workers, starts them together and aggregates their metrics.
+pub struct Controller {
+    args: Args,
+}
+
+impl Controller {
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    pub async fn try_default() -> Result<Self> {
+        ::dotenv::dotenv().ok();
+        let args = Args::try_parse_with_config()?;
+        Self::try_new(args)
+    }
+
+    pub fn try_new(mut args: Args) -> Result<Self> {
+        args.print();
+
+        if args.distributed.workers == 0 {
+            bail!("number of workers should be positive")
+        }
+
+        // Share the run ID to render the same key prefixes on all workers
+        let run_id = args
+            .load_tester
+            .run_id
+            .get_or_insert_with(KeyTemplate::new_run_id);
+        info!("Run ID: {run_id}");
+
+        Ok(Self { args })
+    }
+
+    pub fn spawn(self, signal: FunctionSignal) -> JoinHandle<Result<()>> {
+        spawn(self.loop_forever(signal))
+    }
+
+    async fn loop_forever(self, signal: FunctionSignal) -> Result<()> {
+        let result = self.try_loop_forever(signal.clone()).await;
+        match &result {
+            Ok(()) => signal.terminate(),
+            // Not a crash, but exits with its own code
+            Err(error) if error.is::<SloViolation>() => {
+                error!("{error}");
+                signal.terminate()
+            }
+            Err(error) => {
+                error!("{error}");
+                signal.terminate_on_panic()
+            }
+        }
+        result
+    }
+
+    /// Runs the workers until finished, without terminating the signal.
+    pub async fn try_loop_forever(self, signal: FunctionSignal) -> Result<()> {
+        let Self { args } = self;
+
+        let address = args.distributed.controller_address;
+        let listener = TcpListener::bind(address)
+            .await
+            .map_err(|error| anyhow!("failed to bind controller on {address}: {error}"))?;
+
+        let (phase, mut phase_rx) = watch::channel(Phase::Registering);
+        let state = Arc::new(ControllerState {
+            args,
+            phase,
+            started_at: Mutex::default(),
+            workers: Mutex::default(),
+        });
+        let app = Router::new()
+            .route("/start", get(wait_start))
+            .route("/workers", post(register))
+            .route("/workers/:id/report", put(report))
+            .with_state(state.clone());
+        let server = spawn(async move {
+            ::axum::serve(listener, app)
+                .with_graceful_shutdown(async move {
+                    phase_rx
+                        .wait_for(|phase| *phase == Phase::Stopped)
+                        .await
+                        .ok();
+                })
+                .await
+        });
+
+        info!(
+            "Waiting for {workers} workers: http://{address}",
+            workers = state.args.distributed.workers,
+        );
+        let result = state.wait_finished(&signal).await;
+        state.phase.send_replace(Phase::Stopped);
+        server
+            .await?
+            .map_err(|error| anyhow!("failed to serve controller: {error}"))?;
+        let reports = result?;
+
+        let mut snapshot = MetricsSnapshot::default();
+        for report in &reports {
+            snapshot.merge(&report.snapshot);
+        }
+        info!(
+            "Aggregated the metrics of {count} workers",
+            count = reports.len()
+        );
+        snapshot.print();
+        if let Some(path) = &state.args.distributed.summary_path {
+            let summary = ::serde_json::to_vec(&snapshot.summary())?;
+            fs::write(path, summary).await.map_err(|error| {
+                anyhow!(
+                    "failed to write summary ({path}): {error}",
+                    path = path.display()
+                )
+            })?;
+        }
+
+        let errors = reports
+            .iter()
+            .enumerate()
+            .filter_map(|(id, report)| {
+                report
+                    .error
+                    .as_ref()
+                    .map(|error| format!("worker {id}: {error}"))
+            })
+            .collect::<Vec<_>>();
+        let result = if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "workers failed: {errors}",
+                errors = errors.join(", ")
+            ))
+        };
+
+        // Measure the workload since the first worker has initialized, not the start barrier
+        let started_at = reports
+            .iter()
+            .filter_map(|report| report.started_at)
+            .min()
+            .or(*state.started_at.lock().unwrap())
+            .unwrap_or_else(Utc::now);
+        let elapsed = (Utc::now() - started_at).to_std().unwrap_or_default();
+        let slos = slo::evaluate(&state.args.metrics.slo, &snapshot, elapsed);
+        let report = Reporter::new(&state.args)
+            .map(|reporter| reporter.write(started_at, &snapshot, &result, &slos))
+            .transpose();
+        result?;
+        report?;
+        let slo = slo::check(&slos);
+
+        // The workers keep the objects not to delete the ones of the others in flight
+        let args = &state.args;
+        if !args.load_tester_job.no_cleanup {
+            let backend = backend::try_new(args).await?;
+            let keys = KeyTemplate::try_new(
+                &args.load_tester,
+                args.load_tester.run_id.clone().unwrap_or_default(),
+                args.load_tester_job.total_tasks(),
+            )?;
+            session::cleanup(&*backend, &keys).await?;
+        }
+        slo
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Phase {
+    Registering,
+    Running,
+    Stopped,
+}
+
+struct ControllerState {
+    args: Args,
+    phase: watch::Sender<Phase>,
+    started_at: Mutex<Option<DateTime<Utc>>>,
+    /// Registered workers, by their IDs
+    workers: Mutex<Vec<WorkerState>>,
+}
+
+impl ControllerState {
+    /// Opens the start barrier once all workers have registered and initialized,
+    /// and returns their final reports once all have finished.
+    async fn wait_finished(&self, signal: &FunctionSignal) -> Result<Vec<WorkerReport>> {
+        let total = self.args.distributed.workers;
+        let timeout = self.args.distributed.worker_timeout.into();
+        loop {
+            if signal.is_terminating() {
+                bail!("controller has been terminated before the workers finished")
+            }
+
+            {
+                let mut workers = self.workers.lock().unwrap();
+                if *self.phase.borrow() == Phase::Registering {
+                    // The others would wait for the failed one forever
+                    if let Some((id, error)) =
+                        workers.iter().enumerate().find_map(|(id, worker)| {
+                            worker.report.error.as_ref().map(|error| (id, error))
+                        })
+                    {
+                        bail!("worker {id} failed before the start: {error}")
+                    }
+                    if workers.len() == total && workers.iter().all(|worker| worker.report.ready) {
+                        info!("Starting {total} workers");
+                        // The workers start reporting from now on, however early they have registered
+                        let now = Instant::now();
+                        for worker in workers.iter_mut() {
+                            worker.updated = now;
+                        }
+                        self.started_at.lock().unwrap().replace(Utc::now());
+                        self.phase.send_replace(Phase::Running);
+                    }
+                }
+
+                if workers.len() == total && workers.iter().all(|worker| worker.report.finished) {
+                    break Ok(workers.iter().map(|worker| worker.report.clone()).collect());
+                }
+
+                if *self.phase.borrow() == Phase::Running {
+                    if let Some(id) = workers.iter().position(|worker| {
+                        !worker.report.finished && worker.updated.elapsed() > timeout
+                    }) {
+                        bail!("worker {id} has not reported for {timeout:?}")
+                    }
+                }
+            }
+            sleep(Controller::POLL_INTERVAL).await;
+        }
+    }
+}
+
+struct WorkerState {
+    report: WorkerReport,
+    updated: Instant,
+}
+
+async fn register(State(state): State<Arc<ControllerState>>) -> Response {
+    let args = &state.args;
+    let total = args.distributed.workers;
+
+    let mut workers = state.workers.lock().unwrap();
+    if workers.len() == total {
+        return (
+            StatusCode::CONFLICT,
+            format!("all {total} workers have already registered"),
+        )
+            .into_response();
+    }
+    let id = workers.len();
+    workers.push(WorkerState {
+        report: WorkerReport::default(),
+        updated: Instant::now(),
+    });
+    info!("Registered worker {id} of {total}");
+
+    let mut args = args.clone();
+    args.load_tester.key_offset += id * args.load_tester.step.as_u64() as usize;
+    args.load_tester.seed = args
+        .load_tester
+        .seed
+        .map(|seed| seed.wrapping_add((id as u64) << 32));
+    args.load_tester_job.no_cleanup = true;
+    // The controller reports and asserts the aggregated metrics instead,
+    // and the workers would overwrite the files of each other on a shared volume
+    args.metrics.report = None;
+    args.metrics.slo.clear();
+    args.metrics.timeseries = None;
+    Json(Assignment {
+        args,
+        id,
+        workers: total,
+    })
+    .into_response()
+}
+
+async fn wait_start(State(state): State<Arc<ControllerState>>) -> Response {
+    let mut phase = state.phase.subscribe();
+    match phase
+        .wait_for(|phase| *phase != Phase::Registering)
+        .await
+        .map(|phase| *phase)
+    {
+        Ok(Phase::Running) => StatusCode::OK.into_response(),
+        _ => (StatusCode::SERVICE_UNAVAILABLE, "controller has stopped").into_response(),
+    }
+}
+
+async fn report(
+    State(state): State<Arc<ControllerState>>,
+    Path(id): Path<usize>,
+    Json(report): Json<WorkerReport>,
+) -> Response {
+    let mut workers = state.workers.lock().unwrap();
+    match workers.get_mut(id) {
+        Some(worker) => {
+            if report.finished {
+                info!("Worker {id} has finished");
+            }
+            worker.report = report;
+            worker.updated = Instant::now();
+            StatusCode::OK.into_response()
+        }
+        None => (StatusCode::NOT_FOUND, format!("unknown worker: {id}")).into_response(),
+    }
+}
+
+/// Runs the scenario received from the controller, reporting the metrics back.
+#[derive(Clone)]
+pub struct Worker {
+    client: Client,
+    controller_url: String,
+}
+
+impl Worker {
+    const REGISTER_INTERVAL: Duration = Duration::from_secs(1);
+    /// The controller may have stopped already, e.g. when the other workers have failed
+    const REGISTER_TIMEOUT: Duration = Duration::from_secs(300);
+    const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub fn try_new(args: DistributedArgs) -> Result<Self> {
+        let controller_url = args
+            .controller_url
+            .ok_or_else(|| anyhow!("controller URL is required for the workers"))?;
+
+        Ok(Self {
+            client: Client::new(),
+            controller_url: controller_url.trim_end_matches('/').into(),
+        })
+    }
+
+    pub fn spawn(self, signal: FunctionSignal) -> JoinHandle<Result<()>> {
+        spawn(self.loop_forever(signal))
+    }
+
+    async fn loop_forever(self, signal: FunctionSignal) -> Result<()> {
+        let result = self.try_loop_forever(signal.clone()).await;
+        match &result {
+            Ok(()) => signal.terminate(),
+            Err(error) => {
+                error!("{error}");
+                signal.terminate_on_panic()
+            }
+        }
+        result
+    }
+
+    /// Runs the assigned scenario until finished, without terminating the signal.
+    pub async fn try_loop_forever(self, signal: FunctionSignal) -> Result<()> {
+        let Assignment { args, id, workers } = self.register(&signal).await?;
+        info!("Registered as worker {id} of {workers}");
+
+        let session = match ObjectStorageSession::try_new(args).await {
+            Ok(session) => session,
+            Err(error) => {
+                return self
+                    .finish(id, Err(error), MetricsSnapshot::default(), None)
+                    .await
+            }
+        };
+        let metrics = session.task_metrics().to_vec();
+        let started_at = session.started_at();
+        if let Some(server) = session.metrics_server() {
+            server.spawn(signal.clone());
+        }
+
+        // Initialize before the start barrier, so that all workers start the workload together
+        let ready = Arc::<AtomicBool>::default();
+        let start_barrier = {
+            let metrics = metrics.clone();
+            let ready = ready.clone();
+            let worker = self.clone();
+            async move {
+                ready.store(true, Ordering::SeqCst);
+                let report = WorkerReport {
+                    error: None,
+                    finished: false,
+                    ready: true,
+                    snapshot: MetricsSnapshot::collect(&metrics),
+                    started_at: None,
+                };
+                worker
+                    .report(id, &report)
+                    .await
+                    .map_err(|error| anyhow!("failed to report the initialization: {error}"))?;
+
+                info!("Waiting for the other workers...");
+                Self::send(
+                    worker
+                        .client
+                        .get(format!("{}/start", worker.controller_url)),
+                )
+                .await
+                .map_err(|error| anyhow!("failed to wait for the start: {error}"))
+            }
+        };
+        let session = session.with_start_barrier(start_barrier);
+
+        let mut task_handler = pin!(session.try_loop_forever(signal));
+        let result = loop {
+            select! {
+                result = &mut task_handler => break result,
+                () = sleep(Self::REPORT_INTERVAL) => {
+                    let report = WorkerReport {
+                        error: None,
+                        finished: false,
+                        ready: ready.load(Ordering::SeqCst),
+                        snapshot: MetricsSnapshot::collect(&metrics),
+                        started_at: started_at.get().copied(),
+                    };
+                    if let Err(error) = self.report(id, &report).await {
+                        warn!("failed to report metrics: {error}");
+                    }
+                }
+            }
+        };
+        let snapshot = MetricsSnapshot::collect(&metrics);
+        self.finish(id, result, snapshot, started_at.get().copied())
+            .await
+    }
+
+    /// Reports the final metrics, passing the result through.
+    async fn finish(
+        &self,
+        id: usize,
+        result: Result<()>,
+        snapshot: MetricsSnapshot,
+        started_at: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        let report = WorkerReport {
+            error: result.as_ref().err().map(ToString::to_string),
+            finished: true,
+            ready: started_at.is_some(),
+            snapshot,
+            started_at,
+        };
+        self.report(id, &report)
+            .await
+            .map_err(|error| anyhow!("failed to report the final metrics: {error}"))?;
+        result
+    }
+
+    async fn register(&self, signal: &FunctionSignal) -> Result<Assignment> {
+        let url = format!("{}/workers", self.controller_url);
+        let deadline = Instant::now() + Self::REGISTER_TIMEOUT;
+        loop {
+            match self.client.post(&url).send().await {
+                Ok(response) => {
+                    break Self::check(response)
+                        .await?
+                        .json()
+                        .await
+                        .map_err(|error| anyhow!("failed to parse the assignment: {error}"))
+                }
+                // The controller may not be ready yet
+                Err(error)
+                    if error.is_connect()
+                        && !signal.is_terminating()
+                        && Instant::now() < deadline =>
+                {
+                    warn!("Waiting for the controller: {error}");
+                    sleep(Self::REGISTER_INTERVAL).await;
+                }
+                Err(error) => bail!("failed to register to the controller: {error}"),
+            }
+        }
+    }
+
+    async fn report(&self, id: usize, report: &WorkerReport) -> Result<()> {
+        let url = format!("{}/workers/{id}/report", self.controller_url);
+        Self::send(self.client.put(url).json(report)).await
+    }
+
+    async fn send(request: RequestBuilder) -> Result<()> {
+        Self::check(request.send().await?).await.map(|_| ())
+    }
+
+    async fn check(response: reqwest::Response) -> Result<reqwest::Response> {
+        let status = response.status();
+        if status.is_success() {
+            Ok(response)
+        } else {
+            let body = response.text().await.unwrap_or_default();
+            bail!("{status}: {body}")
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::fs;
+
+use anyhow::{anyhow, bail, Result};
+use byte_unit::Byte;
+use rand::{
+    distributions::{Distribution, Uniform, WeightedIndex},
+    Rng,
+};
+use rand_distr::{LogNormal, Zipf};
+
+use crate::args::{KeyDistribution, SizeDistribution};
+
+pub enum KeySampler {
+    Sequential {
+        len: usize,
+    },
+    Uniform(Uniform<usize>),
+    Zipfian(Zipf<f64>),
+    Hotspot {
+        /// Probability of accessing the hot keys
+        ratio: f64,
+        hot: Uniform<usize>,
+        cold: Option<Uniform<usize>>,
+    },
+}
+
+impl KeySampler {
+    pub fn try_new(distribution: KeyDistribution, len: usize) -> Result<Self> {
+        if len == 0 {
+            bail!("the number of keys should be positive")
+        }
+
+        match distribution {
+            KeyDistribution::Sequential => Ok(Self::Sequential { len }),
+            KeyDistribution::Uniform => Ok(Self::Uniform(Uniform::new(0, len))),
+            KeyDistribution::Zipfian { skew } => Ok(Self::Zipfian(Zipf::new(len as u64, skew)?)),
+            KeyDistribution::Hotspot { requests, keys } => {
+                let hot = ((len as f64 * keys / 100.0).ceil() as usize).clamp(1, len);
+                Ok(Self::Hotspot {
+                    ratio: requests / 100.0,
+                    hot: Uniform::new(0, hot),
+                    cold: (hot < len).then(|| Uniform::new(hot, len)),
+                })
+            }
+        }
+    }
+
+    /// Picks a key of the `counter`-th request.
+    pub fn sample<R>(&self, counter: usize, rng: &mut R) -> usize
+    where
+        R: Rng + ?Sized,
+    {
+        match self {
+            Self::Sequential { len } => counter % len,
+            Self::Uniform(distribution) => distribution.sample(rng),
+            // Zipf draws the ranks starting from 1
+            Self::Zipfian(distribution) => distribution.sample(rng) as usize - 1,
+            Self::Hotspot { ratio, hot, cold } => match cold {
+                Some(cold) if !rng.gen_bool(*ratio) => cold.sample(rng),
+                _ => hot.sample(rng),
+            },
+        }
+    }
+}
+
+pub struct SizeSampler {
+    kind: SizeSamplerKind,
+    max: usize,
+    mean: f64,
+}
+
+enum SizeSamplerKind {
+    Fixed(usize),
+    LogNormal(LogNormal<f64>),
+    Uniform(Uniform<usize>),
+    Weighted {
+        sizes: Vec<usize>,
+        weights: WeightedIndex<u32>,
+    },
+}
+
+impl SizeSampler {
+    pub fn try_new(size: Byte, distribution: Option<&SizeDistribution>) -> Result<Self> {
+        match distribution {
+            None => {
+                let size = size.as_u64() as usize;
+                Ok(Self {
+                    kind: SizeSamplerKind::Fixed(size),
+                    max: size,
+                    mean: size as f64,
+                })
+            }
+            Some(SizeDistribution::Buckets(buckets)) => Self::try_new_weighted(buckets),
+            Some(SizeDistribution::Empirical(path)) => {
+                let content = fs::read_to_string(path).map_err(|error| {
+                    anyhow!(
+                        "failed to read size histogram ({path}): {error}",
+                        path = path.display(),
+                    )
+                })?;
+                let buckets = SizeDistribution::parse_buckets(
+                    content
+                        .lines()
+                        .map(|line| line.split('#').next().unwrap_or_default()),
+                )?;
+                Self::try_new_weighted(&buckets)
+            }
+            Some(SizeDistribution::LogNormal { median, sigma, max }) => {
+                let median = median.as_u64().max(1) as f64;
+                let max = max.as_u64() as usize;
+                Ok(Self {
+                    kind: SizeSamplerKind::LogNormal(LogNormal::new(median.ln(), *sigma)?),
+                    max,
+                    // Ignore the clamped tail
+                    mean: (median * (sigma * sigma / 2.0).exp()).min(max as f64),
+                })
+            }
+            Some(SizeDistribution::Uniform { min, max }) => {
+                let (min, max) = (min.as_u64() as usize, max.as_u64() as usize);
+                Ok(Self {
+                    kind: SizeSamplerKind::Uniform(Uniform::new_inclusive(min, max)),
+                    max,
+                    mean: (min + max) as f64 / 2.0,
+                })
+            }
+        }
+    }
+
+    fn try_new_weighted(buckets: &[(Byte, u32)]) -> Result<Self> {
+        let sizes = buckets
+            .iter()
+            .map(|(size, _)| size.as_u64() as usize)
+            .collect::<Vec<_>>();
+        let total_weight = buckets
+            .iter()
+            .map(|(_, weight)| *weight as f64)
+            .sum::<f64>();
+
+        Ok(Self {
+            max: sizes.iter().copied().max().unwrap_or_default(),
+            mean: buckets
+                .iter()
+                .map(|(size, weight)| size.as_u64() as f64 * *weight as f64)
+                .sum::<f64>()
+                / total_weight,
+            kind: SizeSamplerKind::Weighted {
+                weights: WeightedIndex::new(buckets.iter().map(|(_, weight)| *weight))?,
+                sizes,
+            },
+        })
+    }
+
+    pub const fn max(&self) -> usize {
+        self.max
+    }
+
+    pub const fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    pub fn sample<R>(&self, rng: &mut R) -> usize
+    where
+        R: Rng + ?Sized,
+    {
+        match &self.kind {
+            SizeSamplerKind::Fixed(size) => *size,
+            SizeSamplerKind::LogNormal(distribution) => {
+                (distribution.sample(rng).round() as usize).min(self.max)
+            }
+            SizeSamplerKind::Uniform(distribution) => distribution.sample(rng),
+            SizeSamplerKind::Weighted { sizes, weights } => sizes[weights.sample(rng)],
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use anyhow::{bail, Result};
+use chrono::Utc;
+
+use crate::args::LoadTesterArgs;
+
+/// Renders the object keys from a template such as `/sample/{run_id}/{index}.bin`.
+pub struct KeyTemplate {
+    date: String,
+    depth: usize,
+    offset: usize,
+    prefixes: usize,
+    run_id: String,
+    segments: Vec<Segment>,
+    total_tasks: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    Date,
+    Hash,
+    Index,
+    RunId,
+    Shard,
+}
+
+impl Segment {
+    /// Returns `true` if the segment is rendered equally for all keys.
+    const fn is_constant(&self) -> bool {
+        match self {
+            Self::Literal(_) | Self::Date | Self::RunId => true,
+            Self::Hash | Self::Index | Self::Shard => false,
+        }
+    }
+}
+
+impl KeyTemplate {
+    pub fn try_new(args: &LoadTesterArgs, run_id: String, total_tasks: usize) -> Result<Self> {
+        let LoadTesterArgs {
+            key_depth,
+            key_offset,
+            key_prefixes,
+            key_template,
+            ..
+        } = args;
+
+        let segments = parse_segments(key_template)?;
+        if !segments.contains(&Segment::Index) {
+            bail!("key template should contain {{index}} to make the keys unique: {key_template}")
+        }
+
+        Ok(Self {
+            date: Utc::now().format("%Y-%m-%d").to_string(),
+            depth: *key_depth,
+            offset: *key_offset,
+            prefixes: *key_prefixes,
+            run_id,
+            segments,
+            total_tasks: total_tasks.max(1),
+        })
+    }
+
+    /// Generates a random run ID, if not given.
+    pub fn new_run_id() -> String {
+        format!("{:08x}", ::rand::random::<u32>())
+    }
+
+    pub fn render(&self, index: usize) -> String {
+        // Shard the indices of each worker among its tasks
+        let shard = index % self.total_tasks;
+        let index = self.offset + index;
+        let hash = hash(index as u64);
+
+        let mut key = String::from("/");
+        if self.prefixes > 1 {
+            let width = self.prefix_width();
+            let prefix = hash % self.prefixes as u64;
+            key.push_str(&format!("{prefix:0width$x}/"));
+        }
+
+        let start = key.len();
+        let constant = self.constant_segments();
+        let mut constant_end = start;
+        for (position, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Literal(literal) => key.push_str(literal),
+                Segment::Date => key.push_str(&self.date),
+                Segment::Hash => key.push_str(&format!("{hash:016x}")),
+                Segment::Index => key.push_str(&format!("{index:06}")),
+                Segment::RunId => key.push_str(&self.run_id),
+                Segment::Shard => key.push_str(&shard.to_string()),
+            }
+            if position < constant {
+                constant_end = key.len();
+            }
+        }
+
+        if self.depth > 0 {
+            // Nest the object into the directories right above it,
+            // keeping the common prefix of all the keys to clean them up
+            let at = key[start..]
+                .rfind('/')
+                .map(|at| start + at + 1)
+                .unwrap_or(start)
+                .max(constant_end);
+            let dirs = (0..self.depth)
+                .map(|level| format!("{:02x}/", (hash >> (56 - 8 * (level % 8))) & 0xff))
+                .collect::<String>();
+            key.insert_str(at, &dirs);
+        }
+        key
+    }
+
+    /// Returns the leading `depth` directories of the key, without the leading slash.
+    pub fn render_dirs(&self, index: usize, depth: usize) -> String {
+        self.render(index)
+            .trim_start_matches('/')
+            .split_inclusive('/')
+            .take(depth)
+            .filter(|dir| dir.ends_with('/'))
+            .collect()
+    }
+
+    /// Returns the common prefixes of all the rendered keys, without the leading slash.
+    pub fn prefixes(&self) -> Vec<String> {
+        let mut prefix = String::new();
+        for segment in &self.segments[..self.constant_segments()] {
+            match segment {
+                Segment::Literal(literal) => prefix.push_str(literal),
+                Segment::Date => prefix.push_str(&self.date),
+                Segment::RunId => prefix.push_str(&self.run_id),
+                Segment::Hash | Segment::Index | Segment::Shard => unreachable!(),
+            }
+        }
+
+        if self.prefixes > 1 {
+            let width = self.prefix_width();
+            (0..self.prefixes)
+                .map(|shard| format!("{shard:0width$x}/{prefix}"))
+                .collect()
+        } else {
+            vec![prefix]
+        }
+    }
+
+    /// Returns the number of the leading segments rendered equally for all keys.
+    fn constant_segments(&self) -> usize {
+        self.segments
+            .iter()
+            .take_while(|segment| segment.is_constant())
+            .count()
+    }
+
+    fn prefix_width(&self) -> usize {
+        format!("{:x}", self.prefixes - 1).len()
+    }
+}
+
+fn parse_segments(template: &str) -> Result<Vec<Segment>> {
+    let mut segments = vec![];
+    let mut rest = template.trim_start_matches('/');
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            segments.push(Segment::Literal(rest[..start].into()));
+        }
+        let Some(end) = rest[start..].find('}') else {
+            bail!("unclosed placeholder in key template: {template}")
+        };
+        segments.push(match &rest[start + 1..start + end] {
+            "date" => Segment::Date,
+            "hash" => Segment::Hash,
+            "index" => Segment::Index,
+            "run_id" => Segment::RunId,
+            "shard" | "task" => Segment::Shard,
+            name => bail!("unknown placeholder in key template: {{{name}}}"),
+        });
+        rest = &rest[start + end + 1..];
+    }
+    if !rest.is_empty() {
+        segments.push(Segment::Literal(rest.into()));
+    }
+    Ok(segments)
+}
+
+/// SplitMix64, to spread the keys and the seeds stably across the runs
+pub const fn hash(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend::{Backend, MemoryBackend},
+        session::cleanup,
+    };
+
+    use super::*;
+
+    fn template(key_template: &str, total_tasks: usize) -> Result<KeyTemplate> {
+        let args = LoadTesterArgs {
+            key_template: key_template.into(),
+            ..Default::default()
+        };
+        KeyTemplate::try_new(&args, "run".into(), total_tasks)
+    }
+
+    #[test]
+    fn parse_template() {
+        assert_eq!(
+            parse_segments("/a/{run_id}/{shard}-{index}.bin").unwrap(),
+            [
+                Segment::Literal("a/".into()),
+                Segment::RunId,
+                Segment::Literal("/".into()),
+                Segment::Shard,
+                Segment::Literal("-".into()),
+                Segment::Index,
+                Segment::Literal(".bin".into()),
+            ],
+        );
+
+        assert_eq!(
+            parse_segments("/{task}-{index}").unwrap(),
+            parse_segments("/{shard}-{index}").unwrap(),
+        );
+
+        for key_template in ["/{index}/{worker}", "/{index", "/sample.bin"] {
+            assert!(template(key_template, 1).is_err(), "{key_template}");
+        }
+    }
+
+    #[test]
+    fn render_template() {
+        let keys = template(&LoadTesterArgs::default().key_template, 1).unwrap();
+        assert_eq!(keys.render(7), "/sample/run/000007.bin");
+        assert_eq!(keys.prefixes(), ["sample/run/"]);
+
+        let keys = template("/{shard}/{index}", 4).unwrap();
+        assert_eq!(keys.render(6), "/2/000006");
+        assert_eq!(keys.prefixes(), [""]);
+    }
+
+    #[test]
+    fn render_nested_template() {
+        let args = LoadTesterArgs {
+            key_depth: 2,
+            key_prefixes: 16,
+            ..Default::default()
+        };
+        let keys = KeyTemplate::try_new(&args, "run".into(), 1).unwrap();
+
+        let key = keys.render(3);
+        let parts = key.split('/').collect::<Vec<_>>();
+        assert_eq!(parts.len(), 7, "{key}");
+        assert_eq!(parts[1].len(), 1);
+        assert_eq!(&parts[2..4], ["sample", "run"]);
+        assert_eq!(parts[6], "000003.bin");
+        assert_eq!(keys.render_dirs(3, 3), format!("{}/sample/run/", parts[1]));
+        assert_eq!(keys.prefixes().len(), 16);
+        assert!(keys
+            .prefixes()
+            .iter()
+            .any(|prefix| key[1..].starts_with(prefix.as_str())));
+    }
+
+    #[tokio::test]
+    async fn cleanup_keeps_other_runs() {
+        let backend = MemoryBackend::default();
+        let args = LoadTesterArgs {
+            key_depth: 2,
+            key_template: "/sample/{run_id}-{index}".into(),
+            ..Default::default()
+        };
+        let runs = ["a", "b"].map(|run_id| KeyTemplate::try_new(&args, run_id.into(), 1).unwrap());
+        for keys in &runs {
+            for index in 0..8 {
+                backend.put(&keys.render(index), b"").await.unwrap();
+            }
+        }
+
+        let key = runs[0].render(3);
+        assert!(key.starts_with("/sample/a-"), "{key}");
+        assert_eq!(key.split('/').count(), 5, "{key}");
+        assert_eq!(runs[0].prefixes(), ["sample/a-"]);
+
+        cleanup(&backend, &runs[0]).await.unwrap();
+        let page = backend.list("", None, None, 100).await.unwrap();
+        assert_eq!(page.keys.len(), 8);
+        assert!(page.keys.iter().all(|key| key.starts_with("sample/b-")));
+    }
+}
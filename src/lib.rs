@@ -0,0 +1,21 @@
+pub mod args;
+pub mod backend;
+pub mod distributed;
+pub mod distribution;
+pub mod key;
+pub mod metrics;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod multipart;
+#[cfg(feature = "operator")]
+pub mod operator;
+pub mod report;
+pub mod retry;
+pub mod server;
+pub mod session;
+pub mod sink;
+pub mod slo;
+pub mod stage;
+pub mod timeout;
+pub mod timeseries;
+pub mod verify;
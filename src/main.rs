@@ -1,7 +1,10 @@
-mod args;
-mod session;
-
 use ark_core::signal::FunctionSignal;
+use stressful_object_storage::{
+    args::{Args, Role},
+    distributed::{Controller, Worker},
+    session::ObjectStorageSession,
+    slo::SloViolation,
+};
 use tokio::runtime::Runtime;
 use tracing::{error, info};
 
@@ -24,21 +27,48 @@ async fn main_async() {
     }
 
     info!("Booting...");
-    let session = match self::session::ObjectStorageSession::try_default().await {
-        Ok(session) => session,
+    let distributed = match Args::try_parse_distributed() {
+        Ok(args) => args,
         Err(error) => signal.panic(error).await,
     };
 
     info!("Creating session tasks...");
-    let handler_session = session.spawn(signal.clone());
+    let handlers = match distributed.role {
+        Role::Controller => Controller::try_default()
+            .await
+            .map(|controller| vec![controller.spawn(signal.clone())]),
+        Role::Standalone => ObjectStorageSession::try_default().await.map(|session| {
+            // Serve the metrics until terminated
+            if let Some(server) = session.metrics_server() {
+                server.spawn(signal.clone());
+            }
+            vec![session.spawn(signal.clone())]
+        }),
+        Role::Worker => {
+            Worker::try_new(distributed).map(|worker| vec![worker.spawn(signal.clone())])
+        }
+    };
+    let handlers: Vec<_> = match handlers {
+        Ok(handlers) => handlers,
+        Err(error) => signal.panic(error).await,
+    };
 
     info!("Ready");
     signal.wait_to_terminate().await;
 
     info!("Terminating...");
-    if let Err(error) = handler_session.await {
-        error!("{error}");
-    };
+    let mut is_slo_violated = false;
+    for handler in handlers {
+        match handler.await {
+            Ok(Ok(())) => (),
+            // The errors have been reported by the handlers
+            Ok(Err(error)) => is_slo_violated |= error.is::<SloViolation>(),
+            Err(error) => error!("{error}"),
+        }
+    }
 
+    if is_slo_violated {
+        ::std::process::exit(SloViolation::EXIT_CODE)
+    }
     signal.exit().await
 }
@@ -0,0 +1,492 @@
+use std::{
+    collections::BTreeMap,
+    fmt, io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::Error;
+use hdrhistogram::{CreationError, Histogram};
+use s3::error::S3Error;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{args::Operation, backend::BackendError, timeout::RequestTimeout, verify::Corruption};
+
+#[derive(Default)]
+pub struct TaskMetrics {
+    in_flight: AtomicU64,
+    snapshot: Mutex<MetricsSnapshot>,
+}
+
+impl TaskMetrics {
+    pub fn begin(&self) {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn in_flight(&self) -> u64 {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    pub fn record(&self, operation: Operation, latency: Duration, bytes: u64) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        self.with_operation(operation, |metrics| {
+            metrics.bytes += bytes;
+            metrics
+                .latency
+                .saturating_record(latency.as_micros() as u64)
+        })
+    }
+
+    pub fn record_error(&self, operation: Operation, error: &Error) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        self.with_operation(operation, |metrics| {
+            *metrics.errors.entry(ErrorKind::from(error)).or_default() += 1
+        })
+    }
+
+    /// Records a failed attempt to be retried.
+    pub fn record_retry(&self, operation: Operation, kind: ErrorKind) {
+        self.with_operation(operation, |metrics| {
+            *metrics.retries.entry(kind).or_default() += 1
+        })
+    }
+
+    pub fn record_ttfb(&self, operation: Operation, ttfb: Duration) {
+        self.with_operation(operation, |metrics| {
+            metrics.ttfb.saturating_record(ttfb.as_micros() as u64)
+        })
+    }
+
+    pub fn record_delayed(&self) {
+        self.snapshot.lock().unwrap().delayed += 1
+    }
+
+    pub fn record_dropped(&self) {
+        self.snapshot.lock().unwrap().dropped += 1
+    }
+
+    pub fn record_range_fallback(&self) {
+        self.snapshot.lock().unwrap().range_fallbacks += 1
+    }
+
+    pub fn record_unverified(&self) {
+        self.snapshot.lock().unwrap().unverified += 1
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        self.snapshot.lock().unwrap().clone()
+    }
+
+    fn with_operation(&self, operation: Operation, f: impl FnOnce(&mut OperationMetrics)) {
+        let mut snapshot = self.snapshot.lock().unwrap();
+        f(snapshot.operations.entry(operation).or_default())
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricsSnapshot {
+    /// Number of open-loop arrivals delayed by the outstanding requests limit
+    pub delayed: u64,
+    /// Number of open-loop arrivals dropped by the outstanding requests limit
+    pub dropped: u64,
+    pub operations: BTreeMap<Operation, OperationMetrics>,
+    /// Number of the ranged reads fallen back to the whole objects of unknown sizes
+    #[serde(default)]
+    pub range_fallbacks: u64,
+    /// Number of the verified reads of unknown sizes, whose lengths have not been checked
+    #[serde(default)]
+    pub unverified: u64,
+}
+
+impl MetricsSnapshot {
+    pub fn collect(tasks: &[Arc<TaskMetrics>]) -> Self {
+        let mut merged = Self::default();
+        for task in tasks {
+            merged.merge(&task.snapshot());
+        }
+        merged
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        self.delayed += other.delayed;
+        self.dropped += other.dropped;
+        self.range_fallbacks += other.range_fallbacks;
+        self.unverified += other.unverified;
+        for (operation, metrics) in &other.operations {
+            self.operations
+                .entry(*operation)
+                .or_default()
+                .merge(metrics);
+        }
+    }
+
+    pub fn total_corruptions(&self) -> u64 {
+        self.operations
+            .values()
+            .flat_map(|metrics| &metrics.errors)
+            .filter(|(kind, _)| kind.is_corruption())
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    pub fn summary(&self) -> Vec<OperationSummary> {
+        self.operations
+            .iter()
+            .map(|(operation, metrics)| OperationSummary {
+                bytes: metrics.bytes,
+                count: metrics.latency.len(),
+                errors: metrics.total_errors(),
+                latency_max: metrics.latency.max(),
+                latency_p50: metrics.latency.value_at_quantile(0.5),
+                latency_p90: metrics.latency.value_at_quantile(0.9),
+                latency_p99: metrics.latency.value_at_quantile(0.99),
+                operation: *operation,
+                retries: metrics.total_retries(),
+            })
+            .collect()
+    }
+
+    pub fn print(&self) {
+        info!("Summary:");
+        for (operation, metrics) in &self.operations {
+            metrics.print(*operation);
+        }
+        if self.delayed > 0 || self.dropped > 0 {
+            info!(
+                "arrivals delayed: {delayed} | arrivals dropped: {dropped}",
+                delayed = self.delayed,
+                dropped = self.dropped,
+            );
+        }
+        if self.range_fallbacks > 0 {
+            info!(
+                "ranged reads of whole objects: {range_fallbacks}",
+                range_fallbacks = self.range_fallbacks,
+            );
+        }
+        if self.unverified > 0 {
+            info!(
+                "reads of unknown sizes: {unverified}",
+                unverified = self.unverified,
+            );
+        }
+    }
+}
+
+/// Totals of an operation, compact enough to publish, e.g. on the status of a resource.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "operator", derive(::schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct OperationSummary {
+    pub bytes: u64,
+    /// Number of the succeeded requests
+    pub count: u64,
+    /// Number of the finally failed requests
+    pub errors: u64,
+    /// Latencies in microseconds
+    pub latency_max: u64,
+    pub latency_p50: u64,
+    pub latency_p90: u64,
+    pub latency_p99: u64,
+    pub operation: Operation,
+    pub retries: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OperationMetrics {
+    pub bytes: u64,
+    /// Finally failed requests
+    pub errors: BTreeMap<ErrorKind, u64>,
+    /// Latency of each succeeded request, in microseconds
+    #[serde(with = "histogram")]
+    pub latency: Histogram<u64>,
+    /// Failed attempts that have been retried
+    pub retries: BTreeMap<ErrorKind, u64>,
+    /// Time to the first byte of each succeeded read, in microseconds
+    #[serde(with = "histogram")]
+    pub ttfb: Histogram<u64>,
+}
+
+impl Default for OperationMetrics {
+    fn default() -> Self {
+        Self {
+            bytes: 0,
+            errors: BTreeMap::default(),
+            latency: Self::new_histogram().expect("failed to create a latency histogram"),
+            retries: BTreeMap::default(),
+            ttfb: Self::new_histogram().expect("failed to create a TTFB histogram"),
+        }
+    }
+}
+
+impl OperationMetrics {
+    /// Longest latency to track, in microseconds; the longer ones saturate to it
+    const MAX_LATENCY: u64 = 3_600_000_000;
+
+    fn new_histogram() -> Result<Histogram<u64>, CreationError> {
+        // The auto-resizing histograms would saturate the records at their initial bounds
+        Histogram::new_with_bounds(1, Self::MAX_LATENCY, 3)
+    }
+}
+
+impl OperationMetrics {
+    pub fn merge(&mut self, other: &Self) {
+        self.bytes += other.bytes;
+        for (kind, count) in &other.errors {
+            *self.errors.entry(*kind).or_default() += count;
+        }
+        self.latency
+            .add(&other.latency)
+            .expect("failed to merge latency histograms");
+        for (kind, count) in &other.retries {
+            *self.retries.entry(*kind).or_default() += count;
+        }
+        self.ttfb
+            .add(&other.ttfb)
+            .expect("failed to merge TTFB histograms");
+    }
+
+    /// Leaves the metrics recorded since the earlier ones of the same task.
+    pub fn subtract(&mut self, earlier: &Self) {
+        self.bytes = self.bytes.saturating_sub(earlier.bytes);
+        for (kind, count) in &earlier.errors {
+            if let Some(value) = self.errors.get_mut(kind) {
+                *value = value.saturating_sub(*count);
+            }
+        }
+        self.errors.retain(|_, count| *count > 0);
+        self.latency
+            .subtract(&earlier.latency)
+            .expect("failed to subtract latency histograms");
+        for (kind, count) in &earlier.retries {
+            if let Some(value) = self.retries.get_mut(kind) {
+                *value = value.saturating_sub(*count);
+            }
+        }
+        self.retries.retain(|_, count| *count > 0);
+        self.ttfb
+            .subtract(&earlier.ttfb)
+            .expect("failed to subtract TTFB histograms");
+    }
+
+    pub fn total_errors(&self) -> u64 {
+        self.errors.values().sum()
+    }
+
+    pub fn total_retries(&self) -> u64 {
+        self.retries.values().sum()
+    }
+
+    pub fn quantile(&self, quantile: f64) -> Duration {
+        Duration::from_micros(self.latency.value_at_quantile(quantile))
+    }
+
+    fn print(&self, operation: Operation) {
+        let Self {
+            bytes,
+            errors,
+            latency,
+            retries,
+            ttfb,
+        } = self;
+
+        info!(
+            "[{operation}] count: {count} | bytes: {bytes} | errors: {total_errors} | retries: {total_retries} | p50: {p50:?} | p90: {p90:?} | p99: {p99:?} | p99.9: {p999:?} | max: {max:?}",
+            count = latency.len(),
+            total_errors = self.total_errors(),
+            total_retries = self.total_retries(),
+            p50 = self.quantile(0.5),
+            p90 = self.quantile(0.9),
+            p99 = self.quantile(0.99),
+            p999 = self.quantile(0.999),
+            max = Duration::from_micros(latency.max()),
+        );
+        if !ttfb.is_empty() {
+            info!(
+                "[{operation}] ttfb p50: {p50:?} | p90: {p90:?} | p99: {p99:?} | max: {max:?}",
+                p50 = Duration::from_micros(ttfb.value_at_quantile(0.5)),
+                p90 = Duration::from_micros(ttfb.value_at_quantile(0.9)),
+                p99 = Duration::from_micros(ttfb.value_at_quantile(0.99)),
+                max = Duration::from_micros(ttfb.max()),
+            );
+        }
+        for (kind, count) in errors {
+            info!("[{operation}] errors ({kind}): {count}");
+        }
+        for (kind, count) in retries {
+            info!("[{operation}] retries ({kind}): {count}");
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    Client,
+    Server,
+    Network,
+    Timeout,
+    Mismatch,
+    Truncated,
+    WrongSize,
+    Other,
+}
+
+impl ErrorKind {
+    pub const ALL: [Self; 8] = [
+        Self::Client,
+        Self::Server,
+        Self::Network,
+        Self::Timeout,
+        Self::Mismatch,
+        Self::Truncated,
+        Self::WrongSize,
+        Self::Other,
+    ];
+
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Client => "client",
+            Self::Server => "server",
+            Self::Network => "network",
+            Self::Timeout => "timeout",
+            Self::Mismatch => "mismatch",
+            Self::Truncated => "truncated",
+            Self::WrongSize => "wrong_size",
+            Self::Other => "other",
+        }
+    }
+
+    pub const fn is_corruption(&self) -> bool {
+        matches!(self, Self::Mismatch | Self::Truncated | Self::WrongSize)
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<&Error> for ErrorKind {
+    fn from(error: &Error) -> Self {
+        if let Some(corruption) = error.downcast_ref::<Corruption>() {
+            return match corruption {
+                Corruption::Mismatch => Self::Mismatch,
+                Corruption::Truncated => Self::Truncated,
+                Corruption::WrongSize => Self::WrongSize,
+            };
+        }
+        if error.downcast_ref::<BackendError>().is_some() {
+            return Self::Client;
+        }
+        // Including the connect timeouts deep inside the client
+        if error.downcast_ref::<RequestTimeout>().is_some()
+            || error.chain().any(|cause| {
+                cause
+                    .downcast_ref::<io::Error>()
+                    .is_some_and(|error| error.kind() == io::ErrorKind::TimedOut)
+                    || cause
+                        .downcast_ref::<reqwest::Error>()
+                        .is_some_and(reqwest::Error::is_timeout)
+            })
+        {
+            return Self::Timeout;
+        }
+
+        match error.downcast_ref::<S3Error>() {
+            Some(S3Error::HttpFailWithBody(status, _)) if (400..500).contains(status) => {
+                Self::Client
+            }
+            Some(S3Error::HttpFailWithBody(_, _)) => Self::Server,
+            Some(_) => Self::Network,
+            None if error.is::<reqwest::Error>() => Self::Network,
+            None => Self::Other,
+        }
+    }
+}
+
+/// Serializes the histograms in the compact V2 format, e.g. to send them to the controller.
+mod histogram {
+    use hdrhistogram::{
+        serialization::{Deserializer as HistogramDeserializer, Serializer as _, V2Serializer},
+        Histogram,
+    };
+    use serde::{de, ser, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(histogram: &Histogram<u64>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut buf = vec![];
+        V2Serializer::new()
+            .serialize(histogram, &mut buf)
+            .map_err(ser::Error::custom)?;
+        serializer.serialize_bytes(&buf)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Histogram<u64>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let buf = Vec::<u8>::deserialize(deserializer)?;
+        HistogramDeserializer::new()
+            .deserialize(&mut buf.as_slice())
+            .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_roundtrip() {
+        let task = TaskMetrics::default();
+        for latency in 1..=100 {
+            task.begin();
+            task.record(Operation::Get, Duration::from_millis(latency), 1024);
+        }
+        task.record_retry(Operation::Get, ErrorKind::Server);
+        let snapshot = task.snapshot();
+
+        let json = ::serde_json::to_string(&snapshot).unwrap();
+        let parsed: MetricsSnapshot = ::serde_json::from_str(&json).unwrap();
+        let (expected, metrics) = (
+            &snapshot.operations[&Operation::Get],
+            &parsed.operations[&Operation::Get],
+        );
+        assert_eq!(metrics.bytes, expected.bytes);
+        assert_eq!(metrics.latency, expected.latency);
+        assert_eq!(metrics.retries, expected.retries);
+        assert_eq!(metrics.quantile(0.99), expected.quantile(0.99));
+        assert_eq!(metrics.quantile(0.5).as_millis(), 50);
+    }
+
+    #[test]
+    fn latency_quantiles() {
+        let task = TaskMetrics::default();
+        for latency in 1..=1000 {
+            task.begin();
+            task.record(Operation::Get, Duration::from_millis(latency), 1024);
+        }
+        // Far beyond the initial bounds of an auto-resizing histogram
+        task.begin();
+        task.record(Operation::Get, Duration::from_secs(600), 1024);
+        let metrics = &task.snapshot().operations[&Operation::Get];
+
+        let within = |actual: Duration, expected: Duration| {
+            let error = actual.abs_diff(expected).as_secs_f64() / expected.as_secs_f64();
+            assert!(error < 0.001, "{actual:?} != {expected:?}");
+        };
+        within(metrics.quantile(0.5), Duration::from_millis(501));
+        within(metrics.quantile(0.99), Duration::from_millis(991));
+        within(metrics.quantile(1.0), Duration::from_secs(600));
+    }
+}
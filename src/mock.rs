@@ -0,0 +1,595 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fmt::Write,
+    hash::{Hash, Hasher},
+    net::SocketAddr,
+    ops::Range,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use axum::{
+    body::Bytes,
+    extract::{DefaultBodyLimit, Path, Query, State},
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    routing::any,
+    Router,
+};
+use chrono::{NaiveDateTime, SecondsFormat, Utc};
+use quick_xml::escape::escape;
+use rand::Rng;
+use serde::Deserialize;
+use tokio::{net::TcpListener, spawn, task::JoinHandle, time::sleep};
+use tracing::error;
+
+use crate::{
+    args::CredentialsArgs,
+    backend::{Backend, BackendError, MemoryBackend, Signer, UploadedPart},
+};
+
+/// Faults injected into the responses of the mock server.
+#[derive(Clone, Debug, Default)]
+pub struct MockOptions {
+    /// Probability to fail each request with `503 Slow Down`
+    pub error_rate: f64,
+    /// Delay before responding to each request
+    pub latency: Duration,
+}
+
+/// Minimal S3-compatible server on localhost, keeping the buckets in memory.
+///
+/// Only the path-style requests sos sends are supported, signed with the mock credentials.
+pub struct MockServer {
+    address: SocketAddr,
+    handle: JoinHandle<()>,
+    state: Arc<MockState>,
+}
+
+impl MockServer {
+    pub const ACCESS_KEY: &'static str = "mock";
+    pub const SECRET_KEY: &'static str = "mock";
+
+    pub async fn try_spawn(options: MockOptions) -> Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", 0))
+            .await
+            .map_err(|error| anyhow!("failed to bind mock server: {error}"))?;
+        let address = listener.local_addr()?;
+
+        let state = Arc::new(MockState {
+            buckets: RwLock::default(),
+            errors: AtomicU64::default(),
+            options: RwLock::new(options),
+            requests: AtomicU64::default(),
+        });
+        let app = Router::new()
+            .route("/:bucket", any(serve_bucket))
+            .route("/:bucket/", any(serve_bucket))
+            .route("/:bucket/*key", any(serve_object))
+            .layer(DefaultBodyLimit::disable())
+            .with_state(state.clone());
+
+        let handle = spawn(async move {
+            if let Err(error) = ::axum::serve(listener, app).await {
+                error!("failed to serve mock S3: {error}");
+            }
+        });
+        Ok(Self {
+            address,
+            handle,
+            state,
+        })
+    }
+
+    pub fn endpoint(&self) -> String {
+        format!("http://{address}", address = self.address)
+    }
+
+    pub fn bucket(&self, name: &str) -> Option<Arc<MemoryBackend>> {
+        self.state.bucket(name)
+    }
+
+    pub fn create_bucket(&self, name: &str) -> Arc<MemoryBackend> {
+        self.state.create_bucket(name)
+    }
+
+    /// Replaces the faults injected into the following requests.
+    pub fn set_options(&self, options: MockOptions) {
+        *self.state.options.write().unwrap() = options;
+    }
+
+    /// Number of the received requests, including the failed ones.
+    pub fn requests(&self) -> u64 {
+        self.state.requests.load(Ordering::SeqCst)
+    }
+
+    /// Number of the requests failed by the injected errors.
+    pub fn injected_errors(&self) -> u64 {
+        self.state.errors.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+struct MockState {
+    buckets: RwLock<HashMap<String, Arc<MemoryBackend>>>,
+    errors: AtomicU64,
+    options: RwLock<MockOptions>,
+    requests: AtomicU64,
+}
+
+impl MockState {
+    fn bucket(&self, name: &str) -> Option<Arc<MemoryBackend>> {
+        self.buckets.read().unwrap().get(name).cloned()
+    }
+
+    fn create_bucket(&self, name: &str) -> Arc<MemoryBackend> {
+        self.buckets
+            .write()
+            .unwrap()
+            .entry(name.into())
+            .or_default()
+            .clone()
+    }
+
+    /// Delays the request, and fails it if unlucky or not signed properly.
+    async fn intercept(
+        &self,
+        method: &Method,
+        uri: &Uri,
+        query: &HashMap<String, String>,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Option<Response> {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+
+        let MockOptions {
+            error_rate,
+            latency,
+        } = self.options.read().unwrap().clone();
+        if !latency.is_zero() {
+            sleep(latency).await;
+        }
+        if error_rate > 0.0 && ::rand::thread_rng().gen_bool(error_rate.min(1.0)) {
+            self.errors.fetch_add(1, Ordering::SeqCst);
+            Some(error_response(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "SlowDown",
+                "injected error",
+            ))
+        } else {
+            verify_signature(method, uri, query, headers, body)
+                .err()
+                .map(|error| error_response(StatusCode::FORBIDDEN, "SignatureDoesNotMatch", &error))
+        }
+    }
+}
+
+/// Verifies the AWS Signature Version 4 of the request with the mock credentials.
+fn verify_signature(
+    method: &Method,
+    uri: &Uri,
+    query: &HashMap<String, String>,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<(), String> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| format!("missing header: {name}"))
+    };
+    let authorization = header("authorization")?;
+    let field = |name: &str| {
+        authorization
+            .split([' ', ','])
+            .find_map(|field| field.strip_prefix(name)?.strip_prefix('='))
+            .ok_or_else(|| format!("missing {name} in authorization"))
+    };
+
+    let mut scope = field("Credential")?.split('/');
+    let (Some(access_key), Some(_), Some(region)) = (scope.next(), scope.next(), scope.next())
+    else {
+        return Err("invalid credential scope".into());
+    };
+    if access_key != MockServer::ACCESS_KEY {
+        return Err(format!("unknown access key: {access_key}"));
+    }
+
+    let payload_hash = header("x-amz-content-sha256")?;
+    if payload_hash != Signer::UNSIGNED_PAYLOAD && payload_hash != Signer::hash_payload(body) {
+        return Err("payload hash mismatch".into());
+    }
+    let now = NaiveDateTime::parse_from_str(header("x-amz-date")?, "%Y%m%dT%H%M%SZ")
+        .map_err(|error| format!("invalid date: {error}"))?
+        .and_utc();
+
+    // The signer adds the headers of the payload hash and the date by itself
+    let signed_headers = field("SignedHeaders")?
+        .split(';')
+        .filter(|name| !matches!(*name, "x-amz-content-sha256" | "x-amz-date"))
+        .map(|name| Ok((name, header(name)?.to_string())))
+        .collect::<Result<Vec<_>, String>>()?;
+    let query = query
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect::<Vec<_>>();
+
+    let signer = Signer::new(
+        &CredentialsArgs {
+            access_key: Some(MockServer::ACCESS_KEY.into()),
+            secret_key: Some(MockServer::SECRET_KEY.into()),
+            security_token: None,
+            session_token: None,
+        },
+        region,
+    );
+    let signed = signer.sign(
+        method.as_str(),
+        uri.path(),
+        &query,
+        &signed_headers,
+        payload_hash,
+        now,
+    );
+    match signed.last() {
+        Some((_, expected)) if expected == authorization => Ok(()),
+        _ => Err("signature mismatch".into()),
+    }
+}
+
+async fn serve_bucket(
+    State(state): State<Arc<MockState>>,
+    Path(bucket): Path<String>,
+    method: Method,
+    uri: Uri,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if let Some(response) = state
+        .intercept(&method, &uri, &query, &headers, &body)
+        .await
+    {
+        return response;
+    }
+
+    match method {
+        Method::PUT => {
+            state.create_bucket(&bucket);
+            StatusCode::OK.into_response()
+        }
+        Method::DELETE => {
+            state.buckets.write().unwrap().remove(&bucket);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Method::GET | Method::HEAD => match state.bucket(&bucket) {
+            Some(backend) => list(&*backend, &bucket, &query)
+                .await
+                .unwrap_or_else(backend_error_response),
+            None => no_such_bucket(&bucket),
+        },
+        Method::POST if query.contains_key("delete") => match state.bucket(&bucket) {
+            // Like S3, refuse the batches without the checksum
+            Some(_) if !headers.contains_key("content-md5") => error_response(
+                StatusCode::BAD_REQUEST,
+                "InvalidRequest",
+                "missing Content-MD5",
+            ),
+            Some(backend) => match parse_keys(&body) {
+                Ok(keys) => backend
+                    .delete_many(&keys)
+                    .await
+                    .map(|()| xml_response("<DeleteResult></DeleteResult>".into()))
+                    .unwrap_or_else(backend_error_response),
+                Err(error) => {
+                    error_response(StatusCode::BAD_REQUEST, "MalformedXML", &error.to_string())
+                }
+            },
+            None => no_such_bucket(&bucket),
+        },
+        _ => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+    }
+}
+
+async fn serve_object(
+    State(state): State<Arc<MockState>>,
+    Path((bucket, key)): Path<(String, String)>,
+    method: Method,
+    uri: Uri,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if let Some(response) = state
+        .intercept(&method, &uri, &query, &headers, &body)
+        .await
+    {
+        return response;
+    }
+    let Some(backend) = state.bucket(&bucket) else {
+        return no_such_bucket(&bucket);
+    };
+
+    let upload_id = query.get("uploadId");
+    let result = match (method, upload_id) {
+        (Method::GET, _) => get(&*backend, &key, &headers).await,
+        (Method::HEAD, _) => backend.head(&key).await.map(|len| {
+            (
+                [(header::CONTENT_LENGTH, len.to_string())],
+                [(header::ETAG, etag(&key))],
+            )
+                .into_response()
+        }),
+        (Method::PUT, Some(upload_id)) => match query.get("partNumber").map(|n| n.parse()) {
+            Some(Ok(part_number)) => backend
+                .upload_part(&key, upload_id, part_number, &body)
+                .await
+                .map(|part| [(header::ETAG, format!("\"{}\"", part.etag))].into_response()),
+            _ => Ok(error_response(
+                StatusCode::BAD_REQUEST,
+                "InvalidArgument",
+                "invalid part number",
+            )),
+        },
+        (Method::PUT, None) => backend
+            .put(&key, &body)
+            .await
+            .map(|()| [(header::ETAG, etag(&key))].into_response()),
+        (Method::POST, None) if query.contains_key("uploads") => {
+            backend.create_multipart(&key).await.map(|upload_id| {
+                xml_response(format!(
+                    "<InitiateMultipartUploadResult><Bucket>{bucket}</Bucket><Key>{key}</Key><UploadId>{upload_id}</UploadId></InitiateMultipartUploadResult>",
+                    bucket = escape(&bucket),
+                    key = escape(&key),
+                ))
+            })
+        }
+        (Method::POST, Some(upload_id)) => match parse_parts(&body) {
+            Ok(parts) => backend
+                .complete_multipart(&key, upload_id, parts)
+                .await
+                .map(|()| {
+                    xml_response(format!(
+                        "<CompleteMultipartUploadResult><Bucket>{bucket}</Bucket><Key>{key}</Key><ETag>{etag}</ETag></CompleteMultipartUploadResult>",
+                        bucket = escape(&bucket),
+                        etag = escape(&etag(&key)),
+                        key = escape(&key),
+                    ))
+                }),
+            Err(error) => Ok(error_response(
+                StatusCode::BAD_REQUEST,
+                "MalformedXML",
+                &error.to_string(),
+            )),
+        },
+        (Method::DELETE, Some(upload_id)) => backend
+            .abort_multipart(&key, upload_id)
+            .await
+            .map(|()| StatusCode::NO_CONTENT.into_response()),
+        (Method::DELETE, None) => backend
+            .delete(&key)
+            .await
+            .map(|()| StatusCode::NO_CONTENT.into_response()),
+        _ => Ok(StatusCode::METHOD_NOT_ALLOWED.into_response()),
+    };
+    result.unwrap_or_else(backend_error_response)
+}
+
+async fn get(backend: &dyn Backend, key: &str, headers: &HeaderMap) -> Result<Response> {
+    let len = backend.head(key).await?;
+    let range = match headers.get(header::RANGE) {
+        Some(range) => Some(parse_range(range.to_str()?)?),
+        None => None,
+    };
+    if range.as_ref().is_some_and(|range| range.start >= len) {
+        return Ok((
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            [(header::CONTENT_RANGE, format!("bytes */{len}"))],
+        )
+            .into_response());
+    }
+
+    let mut body = vec![];
+    backend.get(key, range.clone(), &mut body).await?;
+    Ok(match range {
+        Some(range) => (
+            StatusCode::PARTIAL_CONTENT,
+            [(
+                header::CONTENT_RANGE,
+                format!(
+                    "bytes {start}-{end}/{len}",
+                    start = range.start,
+                    end = range.start + body.len() as u64 - 1,
+                ),
+            )],
+            body,
+        )
+            .into_response(),
+        None => body.into_response(),
+    })
+}
+
+async fn list(
+    backend: &dyn Backend,
+    bucket: &str,
+    query: &HashMap<String, String>,
+) -> Result<Response> {
+    let prefix = query.get("prefix").map(String::as_str).unwrap_or_default();
+    let delimiter = query.get("delimiter").map(String::as_str);
+    let token = query.get("continuation-token").cloned();
+    let max_keys = match query.get("max-keys") {
+        Some(max_keys) => max_keys.parse()?,
+        None => 1000,
+    };
+
+    let page = backend
+        .list(prefix, delimiter, token.clone(), max_keys)
+        .await?;
+    let last_modified = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+
+    let mut w = String::new();
+    write!(
+        w,
+        "<ListBucketResult><Name>{bucket}</Name><Prefix>{prefix}</Prefix><KeyCount>{count}</KeyCount><MaxKeys>{max_keys}</MaxKeys><IsTruncated>{is_truncated}</IsTruncated>",
+        bucket = escape(bucket),
+        count = page.keys.len() + page.prefixes.len(),
+        is_truncated = page.next_token.is_some(),
+        prefix = escape(prefix),
+    )?;
+    if let Some(delimiter) = delimiter {
+        write!(w, "<Delimiter>{}</Delimiter>", escape(delimiter))?;
+    }
+    if let Some(token) = &token {
+        write!(
+            w,
+            "<ContinuationToken>{}</ContinuationToken>",
+            escape(token)
+        )?;
+    }
+    if let Some(token) = &page.next_token {
+        write!(
+            w,
+            "<NextContinuationToken>{}</NextContinuationToken>",
+            escape(token),
+        )?;
+    }
+    for key in &page.keys {
+        write!(
+            w,
+            "<Contents><Key>{key}</Key><LastModified>{last_modified}</LastModified><ETag>{etag}</ETag><Size>{size}</Size><StorageClass>STANDARD</StorageClass></Contents>",
+            etag = escape(&etag(key)),
+            key = escape(key),
+            // The object may have been deleted since listed
+            size = backend.head(key).await.unwrap_or_default(),
+        )?;
+    }
+    for prefix in &page.prefixes {
+        write!(
+            w,
+            "<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>",
+            escape(prefix),
+        )?;
+    }
+    w.push_str("</ListBucketResult>");
+    Ok(xml_response(w))
+}
+
+/// Parses the `bytes=START-END` header into the exclusive range.
+fn parse_range(s: &str) -> Result<Range<u64>> {
+    let Some((start, end)) = s
+        .strip_prefix("bytes=")
+        .and_then(|range| range.split_once('-'))
+    else {
+        bail!("invalid range: {s}")
+    };
+    let start = start.trim().parse()?;
+    let end = match end.trim() {
+        "" => u64::MAX,
+        end => end.parse::<u64>()? + 1,
+    };
+    Ok(start..end)
+}
+
+/// Request of `CompleteMultipartUpload`
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct CompleteMultipartUpload {
+    #[serde(default)]
+    part: Vec<CompletedPart>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct CompletedPart {
+    #[serde(default, rename = "ETag")]
+    etag: String,
+    part_number: u32,
+}
+
+/// Request of `DeleteObjects`
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Delete {
+    #[serde(default)]
+    object: Vec<ObjectIdentifier>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ObjectIdentifier {
+    key: String,
+}
+
+/// Parses the part numbers of the `CompleteMultipartUpload` request.
+fn parse_parts(body: &[u8]) -> Result<Vec<UploadedPart>> {
+    let CompleteMultipartUpload { part } = ::quick_xml::de::from_str(::std::str::from_utf8(body)?)?;
+    Ok(part
+        .into_iter()
+        .map(|CompletedPart { etag, part_number }| UploadedPart { etag, part_number })
+        .collect())
+}
+
+/// Parses the keys of the `DeleteObjects` request.
+fn parse_keys(body: &[u8]) -> Result<Vec<String>> {
+    let Delete { object } = ::quick_xml::de::from_str(::std::str::from_utf8(body)?)?;
+    Ok(object
+        .into_iter()
+        .map(|ObjectIdentifier { key }| key)
+        .collect())
+}
+
+fn etag(key: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    format!("\"{hash:016x}\"", hash = hasher.finish())
+}
+
+fn xml_response(body: String) -> Response {
+    (
+        [(header::CONTENT_TYPE, "application/xml")],
+        format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>{body}"),
+    )
+        .into_response()
+}
+
+fn error_response(status: StatusCode, code: &str, message: &str) -> Response {
+    let mut response = xml_response(format!(
+        "<Error><Code>{code}</Code><Message>{message}</Message></Error>",
+        message = escape(message),
+    ));
+    *response.status_mut() = status;
+    response
+}
+
+fn backend_error_response(error: anyhow::Error) -> Response {
+    match error.downcast_ref::<BackendError>() {
+        Some(BackendError::NoSuchKey(_)) => {
+            error_response(StatusCode::NOT_FOUND, "NoSuchKey", &error.to_string())
+        }
+        Some(BackendError::NoSuchUpload(_)) => {
+            error_response(StatusCode::NOT_FOUND, "NoSuchUpload", &error.to_string())
+        }
+        None => error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "InternalError",
+            &error.to_string(),
+        ),
+    }
+}
+
+fn no_such_bucket(bucket: &str) -> Response {
+    error_response(
+        StatusCode::NOT_FOUND,
+        "NoSuchBucket",
+        &format!("no such bucket: {bucket}"),
+    )
+}
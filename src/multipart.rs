@@ -0,0 +1,253 @@
+use std::{mem, ops::Range, sync::Arc};
+
+use anyhow::{bail, Result};
+use byte_unit::Byte;
+use tokio::runtime::Handle;
+
+use crate::{
+    args::LoadTesterArgs,
+    backend::{Backend, UploadedPart},
+};
+
+/// Splits the objects into the parts of the multipart uploads.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MultipartPlanner {
+    part_size: usize,
+    threshold: usize,
+}
+
+impl MultipartPlanner {
+    /// Maximum number of the parts of an object, limited by S3
+    pub const MAX_PARTS: usize = 10_000;
+
+    /// Maximum size of a part, limited by S3
+    pub const MAX_PART_SIZE: u64 = 5 * 1024 * 1024 * 1024; // 5GiB
+
+    pub fn try_new(args: &LoadTesterArgs, max_size: usize) -> Result<Self> {
+        Self::try_from_sizes(
+            args.multipart_threshold.as_u64(),
+            args.multipart_part_size().as_u64(),
+            max_size as u64,
+        )
+    }
+
+    fn try_from_sizes(threshold: u64, part_size: u64, max_size: u64) -> Result<Self> {
+        let minimal = LoadTesterArgs::minimal_multipart_threshold().as_u64();
+        if threshold < minimal {
+            bail!(
+                "multipart threshold should be at least {minimal}, but given: {threshold}",
+                minimal = Byte::from_u64(minimal),
+                threshold = Byte::from_u64(threshold),
+            )
+        }
+        if part_size < minimal {
+            bail!(
+                "multipart part size should be at least {minimal}, but given: {part_size}",
+                minimal = Byte::from_u64(minimal),
+                part_size = Byte::from_u64(part_size),
+            )
+        }
+        if part_size > Self::MAX_PART_SIZE {
+            bail!(
+                "multipart part size should be at most {maximum}, but given: {part_size}",
+                maximum = Byte::from_u64(Self::MAX_PART_SIZE),
+                part_size = Byte::from_u64(part_size),
+            )
+        }
+        if max_size > threshold {
+            let parts = max_size.div_ceil(part_size);
+            if parts > Self::MAX_PARTS as u64 {
+                bail!(
+                    "objects of {max_size} would be split into {parts} parts, exceeding the limit of {limit} parts; increase the multipart part size",
+                    max_size = Byte::from_u64(max_size),
+                    limit = Self::MAX_PARTS,
+                )
+            }
+        }
+
+        Ok(Self {
+            part_size: part_size.try_into()?,
+            threshold: threshold.try_into()?,
+        })
+    }
+
+    pub const fn is_multipart(&self, size: usize) -> bool {
+        size > self.threshold
+    }
+
+    /// Returns the byte ranges of each part.
+    ///
+    /// All parts but the last one have the same part size,
+    /// so the last part is the only one that may be smaller than the S3 minimum.
+    pub fn plan(&self, size: usize) -> Vec<Range<usize>> {
+        (0..size)
+            .step_by(self.part_size)
+            .map(|start| start..size.min(start + self.part_size))
+            .collect()
+    }
+}
+
+/// Multipart upload in progress, aborted unless completed.
+///
+/// The upload is aborted in the background on drop,
+/// as its request may be cancelled by the timeout at any point.
+pub struct MultipartUpload {
+    backend: Arc<dyn Backend>,
+    finished: bool,
+    key: String,
+    upload_id: String,
+}
+
+impl MultipartUpload {
+    pub async fn try_create(backend: Arc<dyn Backend>, key: &str) -> Result<Self> {
+        let upload_id = backend.create_multipart(key).await?;
+        Ok(Self {
+            backend,
+            finished: false,
+            key: key.into(),
+            upload_id,
+        })
+    }
+
+    pub fn upload_id(&self) -> &str {
+        &self.upload_id
+    }
+
+    pub async fn complete(mut self, parts: Vec<UploadedPart>) -> Result<()> {
+        self.backend
+            .complete_multipart(&self.key, &self.upload_id, parts)
+            .await?;
+        self.finished = true;
+        Ok(())
+    }
+
+    pub async fn abort(mut self) {
+        self.finished = true;
+        self.backend
+            .abort_multipart(&self.key, &self.upload_id)
+            .await
+            .ok();
+    }
+}
+
+impl Drop for MultipartUpload {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        // The runtime may be shutting down already
+        if let Ok(handle) = Handle::try_current() {
+            let backend = self.backend.clone();
+            let key = mem::take(&mut self.key);
+            let upload_id = mem::take(&mut self.upload_id);
+            handle.spawn(async move { backend.abort_multipart(&key, &upload_id).await.ok() });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    const MIN: u64 = LoadTesterArgs::minimal_multipart_threshold().as_u64();
+
+    fn planner(part_size: u64) -> MultipartPlanner {
+        MultipartPlanner::try_from_sizes(MIN, part_size, 0).unwrap()
+    }
+
+    #[test]
+    fn plan_exact_multiple() {
+        let part_size = MIN as usize;
+        assert_eq!(
+            planner(MIN).plan(3 * part_size),
+            vec![
+                0..part_size,
+                part_size..2 * part_size,
+                2 * part_size..3 * part_size,
+            ],
+        );
+    }
+
+    #[test]
+    fn plan_short_last_part() {
+        let part_size = MIN as usize;
+        assert_eq!(
+            planner(MIN).plan(part_size + 1),
+            vec![0..part_size, part_size..part_size + 1],
+        );
+    }
+
+    #[test]
+    fn plan_empty_object() {
+        assert!(planner(MIN).plan(0).is_empty());
+    }
+
+    #[test]
+    fn reject_small_threshold() {
+        assert!(MultipartPlanner::try_from_sizes(MIN - 1, MIN, 0).is_err());
+    }
+
+    #[test]
+    fn reject_small_part_size() {
+        assert!(MultipartPlanner::try_from_sizes(MIN, MIN - 1, 0).is_err());
+    }
+
+    #[test]
+    fn reject_large_part_size() {
+        let part_size = MultipartPlanner::MAX_PART_SIZE + 1;
+        assert!(MultipartPlanner::try_from_sizes(MIN, part_size, 0).is_err());
+    }
+
+    #[test]
+    fn reject_too_many_parts() {
+        let max_size = MIN * MultipartPlanner::MAX_PARTS as u64;
+        assert!(MultipartPlanner::try_from_sizes(MIN, MIN, max_size).is_ok());
+        assert!(MultipartPlanner::try_from_sizes(MIN, MIN, max_size + 1).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn plan_is_valid(
+            threshold in MIN..4 * MIN,
+            part_size in MIN..4 * MIN,
+            max_size in 0..(64 * MIN),
+            ratio in 0.0..=1.0f64,
+        ) {
+            let planner = MultipartPlanner::try_from_sizes(threshold, part_size, max_size).unwrap();
+            let size = (max_size as f64 * ratio) as usize;
+            let parts = planner.plan(size);
+
+            // The parts cover the whole object in order
+            let mut pos = 0;
+            for part in &parts {
+                prop_assert_eq!(part.start, pos);
+                prop_assert!(part.start < part.end);
+                pos = part.end;
+            }
+            prop_assert_eq!(pos, size);
+
+            // Only the last part may be smaller than the minimum
+            if let Some((_, parts)) = parts.split_last() {
+                for part in parts {
+                    prop_assert!(part.len() as u64 >= MIN);
+                }
+            }
+            for part in &parts {
+                prop_assert!(part.len() as u64 <= MultipartPlanner::MAX_PART_SIZE);
+            }
+            prop_assert!(parts.len() <= MultipartPlanner::MAX_PARTS);
+        }
+
+        #[test]
+        fn part_limit_is_enforced(
+            part_size in MIN..4 * MIN,
+            parts in 1..2 * MultipartPlanner::MAX_PARTS as u64,
+        ) {
+            let max_size = part_size * parts;
+            let result = MultipartPlanner::try_from_sizes(MIN, part_size, max_size);
+            prop_assert_eq!(result.is_ok(), parts <= MultipartPlanner::MAX_PARTS as u64);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use k8s_openapi::api::core::v1::{EnvFromSource, ResourceRequirements};
+use kube::CustomResource;
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Schema, SchemaObject},
+    JsonSchema,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{args::Args, metrics::OperationSummary};
+
+/// Load test run by a controller and its workers.
+#[derive(Clone, Debug, PartialEq, CustomResource, Serialize, Deserialize, JsonSchema)]
+#[kube(
+    group = "sos.ulagbulag.io",
+    version = "v1alpha1",
+    kind = "StressTest",
+    namespaced,
+    status = "StressTestStatus",
+    shortname = "sos",
+    printcolumn = r#"{
+        "name": "workers",
+        "type": "integer",
+        "jsonPath": ".spec.workers"
+    }"#,
+    printcolumn = r#"{
+        "name": "phase",
+        "type": "string",
+        "jsonPath": ".status.phase"
+    }"#,
+    printcolumn = r#"{
+        "name": "age",
+        "type": "date",
+        "jsonPath": ".metadata.creationTimestamp"
+    }"#
+)]
+#[serde(rename_all = "camelCase")]
+pub struct StressTestSpec {
+    /// Scenario in the serde form of the arguments, like the scenario files,
+    /// but with the byte sizes and counts as strings, e.g. `count: "1M"`
+    #[schemars(schema_with = "preserve_unknown_fields")]
+    pub args: Args,
+
+    /// Environment variables of the pods, e.g. the credentials from a secret
+    #[serde(default)]
+    pub env_from: Vec<EnvFromSource>,
+
+    #[serde(default = "StressTestSpec::default_image")]
+    pub image: String,
+
+    /// Resources of each worker
+    #[serde(default)]
+    pub resources: Option<ResourceRequirements>,
+
+    #[serde(default = "StressTestSpec::default_workers")]
+    pub workers: usize,
+}
+
+impl StressTestSpec {
+    fn default_image() -> String {
+        "quay.io/ulagbulag/sos:latest".into()
+    }
+
+    const fn default_workers() -> usize {
+        1
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StressTestStatus {
+    #[serde(default)]
+    pub message: Option<String>,
+
+    #[serde(default)]
+    pub phase: StressTestPhase,
+
+    /// Aggregated results of the workers, once finished
+    #[serde(default)]
+    pub results: Vec<OperationSummary>,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+pub enum StressTestPhase {
+    #[default]
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl StressTestPhase {
+    pub const fn is_finished(&self) -> bool {
+        matches!(self, Self::Succeeded | Self::Failed)
+    }
+}
+
+fn preserve_unknown_fields(_: &mut SchemaGenerator) -> Schema {
+    Schema::Object(SchemaObject {
+        instance_type: Some(InstanceType::Object.into()),
+        extensions: [("x-kubernetes-preserve-unknown-fields".into(), true.into())]
+            .into_iter()
+            .collect(),
+        ..Default::default()
+    })
+}
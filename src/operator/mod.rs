@@ -0,0 +1,391 @@
+mod crd;
+
+use std::{collections::BTreeMap, error::Error, fmt, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use ark_core::signal::FunctionSignal;
+use futures::StreamExt;
+use k8s_openapi::{
+    api::{
+        batch::v1::{Job, JobSpec, JobStatus},
+        core::v1::{
+            Container, Pod, PodSpec, PodTemplateSpec, Secret, SecretVolumeSource, Service,
+            ServicePort, ServiceSpec, Volume, VolumeMount,
+        },
+    },
+    apimachinery::pkg::apis::meta::v1::ObjectMeta,
+};
+use kube::{
+    api::{ListParams, Patch, PatchParams},
+    runtime::{controller::Action, watcher, Controller},
+    Api, Client, CustomResourceExt, Resource, ResourceExt,
+};
+use serde_json::json;
+use tokio::{spawn, task::JoinHandle};
+use tracing::{info, warn};
+
+pub use self::crd::{StressTest, StressTestPhase, StressTestSpec, StressTestStatus};
+use crate::metrics::OperationSummary;
+
+/// Turns the stress tests into a controller and worker jobs,
+/// and writes their aggregated results back into the status.
+pub struct Operator {
+    client: Client,
+}
+
+impl Operator {
+    pub async fn try_default() -> Result<Self> {
+        let client = Client::try_default()
+            .await
+            .map_err(|error| anyhow!("failed to connect to the kubernetes cluster: {error}"))?;
+        Ok(Self { client })
+    }
+
+    /// Returns the definition of the custom resource in YAML.
+    pub fn crd() -> Result<String> {
+        ::serde_yaml::to_string(&StressTest::crd()).map_err(Into::into)
+    }
+
+    pub fn spawn(self, signal: FunctionSignal) -> JoinHandle<()> {
+        spawn(self.loop_forever(signal))
+    }
+
+    async fn loop_forever(self, signal: FunctionSignal) {
+        let Self { client } = self;
+        let context = Arc::new(Context {
+            client: client.clone(),
+        });
+
+        info!("Watching stress tests...");
+        Controller::new(
+            Api::<StressTest>::all(client.clone()),
+            watcher::Config::default(),
+        )
+        .owns(Api::<Job>::all(client), watcher::Config::default())
+        .graceful_shutdown_on({
+            let signal = signal.clone();
+            async move { signal.wait_to_terminate().await }
+        })
+        .run(reconcile, error_policy, context)
+        .for_each(|result| async move {
+            match result {
+                Ok((test, _)) => info!("Reconciled: {test}"),
+                Err(error) => warn!("failed to reconcile: {error}"),
+            }
+        })
+        .await;
+        signal.terminate()
+    }
+}
+
+pub struct Context {
+    pub client: Client,
+}
+
+/// Failure of a reconciliation, to be retried later.
+#[derive(Debug)]
+pub struct ReconcileError(anyhow::Error);
+
+impl fmt::Display for ReconcileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Error for ReconcileError {}
+
+const CONTROLLER_PORT: u16 = 9900;
+const FIELD_MANAGER: &str = "sos-operator";
+const LABEL_COMPONENT: &str = "app.kubernetes.io/component";
+const LABEL_INSTANCE: &str = "app.kubernetes.io/instance";
+const LABEL_NAME: &str = "app.kubernetes.io/name";
+
+/// Interval to check the jobs in flight, besides their events
+const REQUEUE_INTERVAL: Duration = Duration::from_secs(10);
+
+const SCENARIO_DIR: &str = "/etc/sos";
+const SCENARIO_FILE: &str = "scenario.json";
+const TERMINATION_LOG: &str = "/dev/termination-log";
+
+pub async fn reconcile(
+    test: Arc<StressTest>,
+    context: Arc<Context>,
+) -> Result<Action, ReconcileError> {
+    try_reconcile(&test, &context.client)
+        .await
+        .map_err(ReconcileError)
+}
+
+pub fn error_policy(_: Arc<StressTest>, _: &ReconcileError, _: Arc<Context>) -> Action {
+    Action::requeue(REQUEUE_INTERVAL)
+}
+
+async fn try_reconcile(test: &StressTest, client: &Client) -> Result<Action> {
+    // Never rerun the finished tests
+    if test
+        .status
+        .as_ref()
+        .is_some_and(|status| status.phase.is_finished())
+    {
+        return Ok(Action::await_change());
+    }
+
+    let name = test.name_any();
+    let namespace = test
+        .namespace()
+        .ok_or_else(|| anyhow!("stress test should be namespaced: {name}"))?;
+    let params = PatchParams::apply(FIELD_MANAGER).force();
+
+    let secret = build_secret(test)?;
+    Api::<Secret>::namespaced(client.clone(), &namespace)
+        .patch(&secret.name_any(), &params, &Patch::Apply(&secret))
+        .await?;
+
+    let service = build_service(test);
+    Api::<Service>::namespaced(client.clone(), &namespace)
+        .patch(&service.name_any(), &params, &Patch::Apply(&service))
+        .await?;
+
+    let jobs = Api::<Job>::namespaced(client.clone(), &namespace);
+    let controller = build_controller_job(test);
+    let controller = jobs
+        .patch(&controller.name_any(), &params, &Patch::Apply(&controller))
+        .await?;
+    let workers = build_worker_job(test);
+    jobs.patch(&workers.name_any(), &params, &Patch::Apply(&workers))
+        .await?;
+
+    let status = match controller.status.unwrap_or_default() {
+        JobStatus {
+            succeeded: Some(1..),
+            ..
+        } => {
+            let (results, message) = load_results(client, &namespace, &name).await?;
+            StressTestStatus {
+                message,
+                phase: StressTestPhase::Succeeded,
+                results,
+            }
+        }
+        JobStatus {
+            failed: Some(1..), ..
+        } => {
+            // The controller may have failed before writing the results
+            let (results, _) = load_results(client, &namespace, &name).await?;
+            StressTestStatus {
+                message: Some("controller has failed; see the logs of the pods".into()),
+                phase: StressTestPhase::Failed,
+                results,
+            }
+        }
+        JobStatus {
+            active: Some(1..), ..
+        } => StressTestStatus {
+            phase: StressTestPhase::Running,
+            ..Default::default()
+        },
+        _ => StressTestStatus::default(),
+    };
+
+    let phase = status.phase;
+    if test.status.as_ref() != Some(&status) {
+        info!("Stress test {namespace}/{name}: {phase:?}");
+        Api::<StressTest>::namespaced(client.clone(), &namespace)
+            .patch_status(
+                &name,
+                &PatchParams::default(),
+                &Patch::Merge(json!({ "status": status })),
+            )
+            .await?;
+    }
+
+    Ok(if phase.is_finished() {
+        Action::await_change()
+    } else {
+        Action::requeue(REQUEUE_INTERVAL)
+    })
+}
+
+/// Loads the aggregated results from the termination message of the controller,
+/// along with the reason if they are missing.
+async fn load_results(
+    client: &Client,
+    namespace: &str,
+    name: &str,
+) -> Result<(Vec<OperationSummary>, Option<String>)> {
+    let params = ListParams::default().labels(&format!(
+        "{LABEL_INSTANCE}={name},{LABEL_COMPONENT}=controller"
+    ));
+    let message = Api::<Pod>::namespaced(client.clone(), namespace)
+        .list(&params)
+        .await?
+        .items
+        .into_iter()
+        .filter_map(|pod| pod.status?.container_statuses)
+        .flatten()
+        .find_map(|status| status.state?.terminated?.message);
+
+    let error = match message.map(|message| ::serde_json::from_str(&message)) {
+        Some(Ok(results)) => return Ok((results, None)),
+        // Kubernetes truncates the termination messages to 4 KiB
+        Some(Err(error)) => format!("failed to parse the results, which may be truncated: {error}"),
+        None => "controller has left no results".into(),
+    };
+    warn!("{error}: {namespace}/{name}");
+    Ok((vec![], Some(error)))
+}
+
+fn build_metadata(test: &StressTest, name: String, component: &str) -> ObjectMeta {
+    ObjectMeta {
+        labels: Some(build_labels(test, component)),
+        name: Some(name),
+        namespace: test.namespace(),
+        owner_references: test.controller_owner_ref(&()).map(|owner| vec![owner]),
+        ..Default::default()
+    }
+}
+
+fn build_labels(test: &StressTest, component: &str) -> BTreeMap<String, String> {
+    [
+        (LABEL_COMPONENT, component.to_string()),
+        (LABEL_INSTANCE, test.name_any()),
+        (LABEL_NAME, "sos".into()),
+    ]
+    .into_iter()
+    .map(|(key, value)| (key.into(), value))
+    .collect()
+}
+
+fn controller_name(test: &StressTest) -> String {
+    format!("{}-controller", test.name_any())
+}
+
+fn scenario_name(test: &StressTest) -> String {
+    format!("{}-scenario", test.name_any())
+}
+
+/// Stores the scenario into a secret, as it may contain the credentials.
+fn build_secret(test: &StressTest) -> Result<Secret> {
+    let scenario = ::serde_json::to_string(&test.spec.args)?;
+    Ok(Secret {
+        metadata: build_metadata(test, scenario_name(test), "scenario"),
+        string_data: Some([(SCENARIO_FILE.into(), scenario)].into()),
+        ..Default::default()
+    })
+}
+
+fn build_service(test: &StressTest) -> Service {
+    Service {
+        metadata: build_metadata(test, controller_name(test), "controller"),
+        spec: Some(ServiceSpec {
+            ports: Some(vec![ServicePort {
+                name: Some("controller".into()),
+                port: CONTROLLER_PORT.into(),
+                ..Default::default()
+            }]),
+            selector: Some(build_labels(test, "controller")),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+fn build_controller_job(test: &StressTest) -> Job {
+    let args = [
+        "--config".into(),
+        format!("{SCENARIO_DIR}/{SCENARIO_FILE}"),
+        "--controller-address".into(),
+        format!("0.0.0.0:{CONTROLLER_PORT}"),
+        "--role".into(),
+        "controller".into(),
+        "--summary-path".into(),
+        TERMINATION_LOG.into(),
+        "--workers".into(),
+        test.spec.workers.to_string(),
+    ];
+    let container = Container {
+        args: Some(args.into()),
+        volume_mounts: Some(vec![VolumeMount {
+            mount_path: SCENARIO_DIR.into(),
+            name: "scenario".into(),
+            read_only: Some(true),
+            ..Default::default()
+        }]),
+        ..build_container(test)
+    };
+    let volume = Volume {
+        name: "scenario".into(),
+        secret: Some(SecretVolumeSource {
+            secret_name: Some(scenario_name(test)),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    build_job(
+        test,
+        controller_name(test),
+        "controller",
+        1,
+        container,
+        vec![volume],
+    )
+}
+
+fn build_worker_job(test: &StressTest) -> Job {
+    let args = [
+        "--controller-url".into(),
+        format!("http://{}:{CONTROLLER_PORT}", controller_name(test)),
+        "--role".into(),
+        "worker".into(),
+    ];
+    let container = Container {
+        args: Some(args.into()),
+        resources: test.spec.resources.clone(),
+        ..build_container(test)
+    };
+    let name = format!("{}-workers", test.name_any());
+    build_job(test, name, "worker", test.spec.workers, container, vec![])
+}
+
+fn build_container(test: &StressTest) -> Container {
+    Container {
+        env_from: Some(test.spec.env_from.clone()),
+        image: Some(test.spec.image.clone()),
+        name: "sos".into(),
+        ..Default::default()
+    }
+}
+
+fn build_job(
+    test: &StressTest,
+    name: String,
+    component: &str,
+    pods: usize,
+    container: Container,
+    volumes: Vec<Volume>,
+) -> Job {
+    let pods = Some(pods as i32);
+    Job {
+        metadata: build_metadata(test, name, component),
+        spec: Some(JobSpec {
+            // The controller fails the whole run on any failure
+            backoff_limit: Some(0),
+            completions: pods,
+            parallelism: pods,
+            template: PodTemplateSpec {
+                metadata: Some(ObjectMeta {
+                    labels: Some(build_labels(test, component)),
+                    ..Default::default()
+                }),
+                spec: Some(PodSpec {
+                    containers: vec![container],
+                    restart_policy: Some("Never".into()),
+                    volumes: Some(volumes),
+                    ..Default::default()
+                }),
+            },
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
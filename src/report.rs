@@ -0,0 +1,300 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+    args::{Args, Operation},
+    metrics::{ErrorKind, MetricsSnapshot},
+    slo::SloResult,
+};
+
+/// Writes the final report of a run, if requested.
+pub struct Reporter {
+    args: Args,
+    path: PathBuf,
+}
+
+impl Reporter {
+    pub fn new(args: &Args) -> Option<Self> {
+        args.metrics.report.clone().map(|path| Self {
+            args: args.clone(),
+            path,
+        })
+    }
+
+    /// Writes the report of the run finished now.
+    pub fn write(
+        &self,
+        started_at: DateTime<Utc>,
+        snapshot: &MetricsSnapshot,
+        result: &Result<()>,
+        slos: &[SloResult],
+    ) -> Result<()> {
+        let error = result.as_ref().err().map(ToString::to_string);
+        Report::new(&self.args, started_at, Utc::now(), snapshot, error)
+            .with_slos(slos)
+            .write(&self.path)
+    }
+}
+
+/// Final report of a run, to archive and compare the results, e.g. on CI.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    /// Effective arguments, with the credentials hidden
+    pub args: Args,
+    /// Error of the failed run
+    pub error: Option<String>,
+    pub finished_at: DateTime<Utc>,
+    pub operations: Vec<OperationReport>,
+    /// Results of the service level objectives, if any
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub slos: Vec<SloResult>,
+    pub started_at: DateTime<Utc>,
+}
+
+impl Report {
+    pub fn new(
+        args: &Args,
+        started_at: DateTime<Utc>,
+        finished_at: DateTime<Utc>,
+        snapshot: &MetricsSnapshot,
+        error: Option<String>,
+    ) -> Self {
+        let mut args = args.clone();
+        args.credentials = args.credentials.redacted();
+
+        let elapsed = (finished_at - started_at)
+            .to_std()
+            .unwrap_or_default()
+            .as_secs_f64();
+        let per_sec = |value: u64| {
+            if elapsed > 0.0 {
+                value as f64 / elapsed
+            } else {
+                0.0
+            }
+        };
+
+        let operations = snapshot
+            .operations
+            .iter()
+            .map(|(operation, metrics)| {
+                let count = metrics.latency.len();
+                OperationReport {
+                    bytes: metrics.bytes,
+                    bytes_per_sec: per_sec(metrics.bytes),
+                    count,
+                    errors: metrics.errors.clone(),
+                    latency_max: metrics.latency.max(),
+                    latency_p50: metrics.latency.value_at_quantile(0.5),
+                    latency_p90: metrics.latency.value_at_quantile(0.9),
+                    latency_p99: metrics.latency.value_at_quantile(0.99),
+                    latency_p999: metrics.latency.value_at_quantile(0.999),
+                    operation: *operation,
+                    ops_per_sec: per_sec(count),
+                    retries: metrics.total_retries(),
+                }
+            })
+            .collect();
+
+        Self {
+            args,
+            error,
+            finished_at,
+            operations,
+            slos: Vec::new(),
+            started_at,
+        }
+    }
+
+    pub fn with_slos(mut self, slos: &[SloResult]) -> Self {
+        self.slos = slos.to_vec();
+        self
+    }
+
+    /// Writes the report as CSV if the path ends with `.csv`, or JSON otherwise.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let is_csv = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+        let buf = if is_csv {
+            self.to_csv().into_bytes()
+        } else {
+            ::serde_json::to_vec_pretty(self)?
+        };
+
+        fs::write(path, buf).map_err(|error| {
+            anyhow!(
+                "failed to write report ({path}): {error}",
+                path = path.display()
+            )
+        })?;
+        info!("Written report: {path}", path = path.display());
+        Ok(())
+    }
+
+    /// Renders a row per operation; the arguments and the SLOs are left to the JSON report.
+    pub fn to_csv(&self) -> String {
+        let mut buf = String::from("operation,started_at,finished_at,count,bytes,errors");
+        for kind in ErrorKind::ALL {
+            write!(buf, ",errors_{kind}").unwrap();
+        }
+        buf.push_str(",retries,latency_p50_us,latency_p90_us,latency_p99_us,latency_p999_us,latency_max_us,ops_per_sec,bytes_per_sec\n");
+
+        let started_at = self.started_at.to_rfc3339_opts(SecondsFormat::Millis, true);
+        let finished_at = self
+            .finished_at
+            .to_rfc3339_opts(SecondsFormat::Millis, true);
+        for report in &self.operations {
+            let OperationReport {
+                bytes,
+                bytes_per_sec,
+                count,
+                errors,
+                latency_max,
+                latency_p50,
+                latency_p90,
+                latency_p99,
+                latency_p999,
+                operation,
+                ops_per_sec,
+                retries,
+            } = report;
+
+            write!(
+                buf,
+                "{operation},{started_at},{finished_at},{count},{bytes},{total_errors}",
+                total_errors = report.total_errors(),
+            )
+            .unwrap();
+            for kind in ErrorKind::ALL {
+                write!(buf, ",{}", errors.get(&kind).unwrap_or(&0)).unwrap();
+            }
+            writeln!(
+                buf,
+                ",{retries},{latency_p50},{latency_p90},{latency_p99},{latency_p999},{latency_max},{ops_per_sec:.3},{bytes_per_sec:.3}",
+            )
+            .unwrap();
+        }
+        buf
+    }
+}
+
+/// Totals of an operation over the whole run.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OperationReport {
+    pub bytes: u64,
+    pub bytes_per_sec: f64,
+    /// Number of the succeeded requests
+    pub count: u64,
+    /// Finally failed requests, by their classes
+    pub errors: BTreeMap<ErrorKind, u64>,
+    /// Latencies in microseconds
+    pub latency_max: u64,
+    pub latency_p50: u64,
+    pub latency_p90: u64,
+    pub latency_p99: u64,
+    pub latency_p999: u64,
+    pub operation: Operation,
+    pub ops_per_sec: f64,
+    pub retries: u64,
+}
+
+impl OperationReport {
+    pub fn total_errors(&self) -> u64 {
+        self.errors.values().sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use anyhow::anyhow;
+    use clap::Parser;
+
+    use super::*;
+    use crate::metrics::TaskMetrics;
+
+    fn report() -> Report {
+        let args = Args::try_parse_from([
+            "sos",
+            "--access-key",
+            "AKIA",
+            "--bucket-name",
+            "sos-test",
+            "--secret-key",
+            "secret",
+        ])
+        .unwrap();
+
+        let task = TaskMetrics::default();
+        for _ in 0..10 {
+            task.begin();
+            task.record(Operation::Put, Duration::from_millis(5), 1000);
+        }
+        task.begin();
+        task.record_error(Operation::Put, &anyhow!("unknown"));
+
+        let started_at = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let finished_at = started_at + Duration::from_secs(2);
+        Report::new(&args, started_at, finished_at, &task.snapshot(), None)
+    }
+
+    #[test]
+    fn report_hides_credentials() {
+        let report = ::serde_json::to_value(report()).unwrap();
+        assert_eq!(report["args"]["accessKey"], "(hidden)");
+        assert_eq!(report["args"]["secretKey"], "(hidden)");
+        assert_eq!(report["args"]["sessionToken"], ::serde_json::Value::Null);
+        assert_eq!(report["args"]["bucketName"], "sos-test");
+        assert_eq!(report["startedAt"], "2023-11-14T22:13:20Z");
+        assert_eq!(report["operations"][0]["errors"]["other"], 1);
+        assert!(report.get("slos").is_none());
+    }
+
+    #[test]
+    fn report_slos() {
+        let slos = [SloResult {
+            actual: Some("5ms".into()),
+            passed: true,
+            slo: "p99(put) < 10ms".into(),
+        }];
+        let report = ::serde_json::to_value(report().with_slos(&slos)).unwrap();
+        assert_eq!(report["slos"][0]["passed"], true);
+        assert_eq!(report["slos"][0]["slo"], "p99(put) < 10ms");
+    }
+
+    #[test]
+    fn report_to_csv() {
+        let report = report();
+        assert_eq!(report.operations[0].ops_per_sec, 5.0);
+        assert_eq!(report.operations[0].bytes_per_sec, 5000.0);
+
+        let csv = report.to_csv();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        let header = lines[0].split(',').collect::<Vec<_>>();
+        let row = lines[1].split(',').collect::<Vec<_>>();
+        assert_eq!(header.len(), row.len());
+
+        let column = |name| row[header.iter().position(|column| *column == name).unwrap()];
+        assert_eq!(column("operation"), "put");
+        assert_eq!(column("started_at"), "2023-11-14T22:13:20.000Z");
+        assert_eq!(column("count"), "10");
+        assert_eq!(column("errors"), "1");
+        assert_eq!(column("errors_other"), "1");
+        assert_eq!(column("errors_server"), "0");
+        assert_eq!(column("ops_per_sec"), "5.000");
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use rand::Rng;
+
+use crate::{
+    args::{LoadTesterJobArgs, RetryClass},
+    metrics::ErrorKind,
+};
+
+/// Retries the failed requests with an exponential backoff and a full jitter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    backoff: Duration,
+    backoff_max: Duration,
+    classes: Vec<RetryClass>,
+    max_attempts: usize,
+}
+
+impl RetryPolicy {
+    pub fn new(args: &LoadTesterJobArgs) -> Self {
+        Self {
+            backoff: args.retry_backoff.into(),
+            backoff_max: args.retry_backoff_max.into(),
+            classes: args.retry_on.clone(),
+            max_attempts: args.retry_max_attempts,
+        }
+    }
+
+    /// Returns the delay before retrying the failed attempt (1-based),
+    /// or `None` if the error should not be retried.
+    pub fn backoff(&self, attempt: usize, kind: ErrorKind, rng: &mut impl Rng) -> Option<Duration> {
+        if attempt >= self.max_attempts || !self.is_retryable(kind) {
+            return None;
+        }
+
+        let exponent = (attempt - 1).min(u32::BITS as usize - 1) as u32;
+        let ceiling = self
+            .backoff
+            .saturating_mul(1 << exponent)
+            .min(self.backoff_max);
+        Some(ceiling.mul_f64(rng.gen()))
+    }
+
+    fn is_retryable(&self, kind: ErrorKind) -> bool {
+        self.classes.iter().any(|class| match class {
+            RetryClass::Client => kind == ErrorKind::Client,
+            RetryClass::Network => kind == ErrorKind::Network,
+            RetryClass::Server => kind == ErrorKind::Server,
+            RetryClass::Timeout => kind == ErrorKind::Timeout,
+        })
+    }
+}
+
+/// Tolerates the failed requests while their rate is within the budget.
+#[derive(Debug)]
+pub struct ErrorBudget {
+    failures: AtomicU64,
+    percent: f64,
+    requests: AtomicU64,
+}
+
+impl ErrorBudget {
+    /// Number of the requests before enforcing the budget,
+    /// not to abort on the very first failures
+    const MIN_REQUESTS: u64 = 100;
+
+    pub fn try_new(percent: f64) -> Result<Self> {
+        if !(0.0..=100.0).contains(&percent) {
+            bail!("error budget should be between 0 and 100%, but given: {percent}")
+        }
+        Ok(Self {
+            failures: AtomicU64::default(),
+            percent,
+            requests: AtomicU64::default(),
+        })
+    }
+
+    pub fn record_success(&self) {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Records a finally failed request, failing if the budget is exhausted.
+    pub fn record_failure(&self) -> Result<()> {
+        let failures = self.failures.fetch_add(1, Ordering::SeqCst) + 1;
+        let requests = self.requests.fetch_add(1, Ordering::SeqCst) + 1;
+
+        let percent = 100.0 * failures as f64 / requests as f64;
+        if requests >= Self::MIN_REQUESTS && percent > self.percent {
+            bail!(
+                "error budget exhausted: {failures} of {requests} requests failed ({percent:.1}% > {budget}%)",
+                budget = self.percent,
+            )
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    use super::*;
+
+    fn policy(max_attempts: usize) -> RetryPolicy {
+        RetryPolicy {
+            backoff: Duration::from_millis(100),
+            backoff_max: Duration::from_secs(1),
+            classes: vec![RetryClass::Network, RetryClass::Server],
+            max_attempts,
+        }
+    }
+
+    #[test]
+    fn backoff_is_bounded() {
+        let policy = policy(usize::MAX);
+        let mut rng = SmallRng::seed_from_u64(0);
+        for attempt in 1..100 {
+            let ceiling = Duration::from_millis(100)
+                .saturating_mul(1 << (attempt - 1).min(31))
+                .min(Duration::from_secs(1));
+            let backoff = policy
+                .backoff(attempt, ErrorKind::Server, &mut rng)
+                .unwrap();
+            assert!(backoff <= ceiling, "{attempt}: {backoff:?} > {ceiling:?}");
+        }
+    }
+
+    #[test]
+    fn backoff_stops_retrying() {
+        let policy = policy(3);
+        let mut rng = SmallRng::seed_from_u64(0);
+        assert!(policy.backoff(1, ErrorKind::Network, &mut rng).is_some());
+        assert!(policy.backoff(2, ErrorKind::Network, &mut rng).is_some());
+        assert!(policy.backoff(3, ErrorKind::Network, &mut rng).is_none());
+        assert!(policy.backoff(1, ErrorKind::Client, &mut rng).is_none());
+        assert!(policy.backoff(1, ErrorKind::Mismatch, &mut rng).is_none());
+    }
+
+    #[test]
+    fn error_budget() {
+        let budget = ErrorBudget::try_new(10.0).unwrap();
+        // Tolerate the first failures
+        for _ in 0..10 {
+            budget.record_failure().unwrap();
+        }
+        for _ in 0..90 {
+            budget.record_success();
+        }
+        assert!(budget.record_failure().is_err());
+
+        assert!(ErrorBudget::try_new(-1.0).is_err());
+        assert!(ErrorBudget::try_new(101.0).is_err());
+    }
+}
@@ -0,0 +1,251 @@
+use std::{fmt::Write, net::SocketAddr, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use ark_core::signal::FunctionSignal;
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use hdrhistogram::Histogram;
+use tokio::{net::TcpListener, spawn, task::JoinHandle};
+use tracing::{error, info};
+
+use crate::{
+    args::Mode,
+    metrics::{MetricsSnapshot, OperationMetrics, TaskMetrics},
+};
+
+pub struct MetricsServer {
+    pub address: SocketAddr,
+    pub mode: Mode,
+    pub tasks: Vec<Arc<TaskMetrics>>,
+}
+
+impl MetricsServer {
+    const CONTENT_TYPE: &'static str = "text/plain; version=0.0.4";
+
+    /// Upper bounds of the latency buckets, in seconds
+    const LATENCY_BUCKETS: &'static [f64] = &[
+        0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+    ];
+
+    pub fn spawn(self, signal: FunctionSignal) -> JoinHandle<()> {
+        spawn(self.loop_forever(signal))
+    }
+
+    async fn loop_forever(self, signal: FunctionSignal) {
+        if let Err(error) = self.try_loop_forever(signal.clone()).await {
+            error!("{error}");
+            signal.terminate_on_panic()
+        }
+    }
+
+    async fn try_loop_forever(self, signal: FunctionSignal) -> Result<()> {
+        let address = self.address;
+        let listener = TcpListener::bind(address)
+            .await
+            .map_err(|error| anyhow!("failed to bind metrics server on {address}: {error}"))?;
+
+        let app = Router::new()
+            .route("/metrics", get(serve_metrics))
+            .with_state(Arc::new(self));
+
+        info!("Serving metrics: http://{address}/metrics");
+        ::axum::serve(listener, app)
+            .with_graceful_shutdown(async move { signal.wait_to_terminate().await })
+            .await
+            .map_err(|error| anyhow!("failed to serve metrics: {error}"))
+    }
+
+    fn render(&self) -> Result<String, std::fmt::Error> {
+        let Self {
+            address: _,
+            mode,
+            tasks,
+        } = self;
+
+        let snapshots = tasks
+            .iter()
+            .map(|task| (task.in_flight(), task.snapshot()))
+            .collect::<Vec<_>>();
+        let mut w = String::new();
+
+        writeln!(
+            w,
+            "# HELP sos_operations_total Number of succeeded requests (use rate() for ops/s)."
+        )?;
+        writeln!(w, "# TYPE sos_operations_total counter")?;
+        for (task, (_, snapshot)) in snapshots.iter().enumerate() {
+            for (operation, metrics) in &snapshot.operations {
+                writeln!(
+                    w,
+                    "sos_operations_total{{mode=\"{mode}\",task=\"{task}\",operation=\"{operation}\"}} {count}",
+                    count = metrics.latency.len(),
+                )?;
+            }
+        }
+
+        writeln!(
+            w,
+            "# HELP sos_bytes_total Number of transferred bytes (use rate() for bytes/s)."
+        )?;
+        writeln!(w, "# TYPE sos_bytes_total counter")?;
+        for (task, (_, snapshot)) in snapshots.iter().enumerate() {
+            for (operation, metrics) in &snapshot.operations {
+                writeln!(
+                    w,
+                    "sos_bytes_total{{mode=\"{mode}\",task=\"{task}\",operation=\"{operation}\"}} {bytes}",
+                    bytes = metrics.bytes,
+                )?;
+            }
+        }
+
+        writeln!(
+            w,
+            "# HELP sos_in_flight_requests Number of requests waiting for responses."
+        )?;
+        writeln!(w, "# TYPE sos_in_flight_requests gauge")?;
+        for (task, (in_flight, _)) in snapshots.iter().enumerate() {
+            writeln!(
+                w,
+                "sos_in_flight_requests{{mode=\"{mode}\",task=\"{task}\"}} {in_flight}",
+            )?;
+        }
+
+        writeln!(
+            w,
+            "# HELP sos_arrivals_delayed_total Number of open-loop arrivals delayed by the outstanding requests limit."
+        )?;
+        writeln!(w, "# TYPE sos_arrivals_delayed_total counter")?;
+        for (task, (_, snapshot)) in snapshots.iter().enumerate() {
+            writeln!(
+                w,
+                "sos_arrivals_delayed_total{{mode=\"{mode}\",task=\"{task}\"}} {delayed}",
+                delayed = snapshot.delayed,
+            )?;
+        }
+
+        writeln!(
+            w,
+            "# HELP sos_arrivals_dropped_total Number of open-loop arrivals dropped by the outstanding requests limit."
+        )?;
+        writeln!(w, "# TYPE sos_arrivals_dropped_total counter")?;
+        for (task, (_, snapshot)) in snapshots.iter().enumerate() {
+            writeln!(
+                w,
+                "sos_arrivals_dropped_total{{mode=\"{mode}\",task=\"{task}\"}} {dropped}",
+                dropped = snapshot.dropped,
+            )?;
+        }
+
+        writeln!(
+            w,
+            "# HELP sos_errors_total Number of finally failed requests."
+        )?;
+        writeln!(w, "# TYPE sos_errors_total counter")?;
+        for (task, (_, snapshot)) in snapshots.iter().enumerate() {
+            for (operation, metrics) in &snapshot.operations {
+                for (kind, count) in &metrics.errors {
+                    writeln!(
+                        w,
+                        "sos_errors_total{{mode=\"{mode}\",task=\"{task}\",operation=\"{operation}\",kind=\"{kind}\"}} {count}",
+                    )?;
+                }
+            }
+        }
+
+        writeln!(
+            w,
+            "# HELP sos_retries_total Number of failed attempts that have been retried."
+        )?;
+        writeln!(w, "# TYPE sos_retries_total counter")?;
+        for (task, (_, snapshot)) in snapshots.iter().enumerate() {
+            for (operation, metrics) in &snapshot.operations {
+                for (kind, count) in &metrics.retries {
+                    writeln!(
+                        w,
+                        "sos_retries_total{{mode=\"{mode}\",task=\"{task}\",operation=\"{operation}\",kind=\"{kind}\"}} {count}",
+                    )?;
+                }
+            }
+        }
+
+        writeln!(
+            w,
+            "# HELP sos_request_duration_seconds Latency of succeeded requests."
+        )?;
+        writeln!(w, "# TYPE sos_request_duration_seconds histogram")?;
+        for (task, (_, snapshot)) in snapshots.iter().enumerate() {
+            render_histogram(
+                &mut w,
+                "sos_request_duration_seconds",
+                *mode,
+                task,
+                snapshot,
+                |metrics| Some(&metrics.latency),
+            )?;
+        }
+
+        writeln!(
+            w,
+            "# HELP sos_time_to_first_byte_seconds Time to the first byte of succeeded reads."
+        )?;
+        writeln!(w, "# TYPE sos_time_to_first_byte_seconds histogram")?;
+        for (task, (_, snapshot)) in snapshots.iter().enumerate() {
+            render_histogram(
+                &mut w,
+                "sos_time_to_first_byte_seconds",
+                *mode,
+                task,
+                snapshot,
+                // Only the reads have the first byte
+                |metrics| Some(&metrics.ttfb).filter(|ttfb| !ttfb.is_empty()),
+            )?;
+        }
+        Ok(w)
+    }
+}
+
+fn render_histogram(
+    w: &mut String,
+    name: &str,
+    mode: Mode,
+    task: usize,
+    snapshot: &MetricsSnapshot,
+    histogram: impl Fn(&OperationMetrics) -> Option<&Histogram<u64>>,
+) -> Result<(), std::fmt::Error> {
+    for (operation, metrics) in &snapshot.operations {
+        let labels = format!("mode=\"{mode}\",task=\"{task}\",operation=\"{operation}\"");
+        let Some(latency) = histogram(metrics) else {
+            continue;
+        };
+
+        for bucket in MetricsServer::LATENCY_BUCKETS {
+            let upper = (bucket * 1_000_000.0) as u64;
+            let count = latency.count_between(0, upper);
+            writeln!(w, "{name}_bucket{{{labels},le=\"{bucket}\"}} {count}",)?;
+        }
+        writeln!(
+            w,
+            "{name}_bucket{{{labels},le=\"+Inf\"}} {count}",
+            count = latency.len(),
+        )?;
+        writeln!(
+            w,
+            "{name}_sum{{{labels}}} {sum}",
+            sum = latency.mean() * latency.len() as f64 / 1_000_000.0,
+        )?;
+        writeln!(w, "{name}_count{{{labels}}} {count}", count = latency.len(),)?;
+    }
+    Ok(())
+}
+
+async fn serve_metrics(State(server): State<Arc<MetricsServer>>) -> impl IntoResponse {
+    match server.render() {
+        Ok(body) => Ok(([(header::CONTENT_TYPE, MetricsServer::CONTENT_TYPE)], body)),
+        Err(error) => Err((StatusCode::INTERNAL_SERVER_ERROR, error.to_string())),
+    }
+}
@@ -1,141 +1,429 @@
 use std::{
     convert::identity,
     fmt::Write,
+    future::Future,
+    ops::Range,
+    pin::pin,
     sync::{
         atomic::{AtomicU64, AtomicU8, Ordering},
-        Arc,
+        Arc, Mutex, OnceLock,
     },
-    time::{Duration, Instant},
+    time::Duration,
 };
 
 use anyhow::{anyhow, bail, Result};
 use ark_core::signal::FunctionSignal;
 use byte_unit::{Byte, UnitType};
-use clap::Parser;
-use futures::{stream::FuturesUnordered, FutureExt, TryFutureExt, TryStreamExt};
+use chrono::{DateTime, Utc};
+use futures::{
+    future::BoxFuture,
+    stream::{self, FuturesUnordered},
+    FutureExt, StreamExt, TryStreamExt,
+};
 use indicatif::{ProgressBar, ProgressState, ProgressStyle};
-use rand::{rngs::SmallRng, RngCore, SeedableRng};
-use s3::{serde_types::InitiateMultipartUploadResponse, Bucket, BucketConfiguration};
-use tokio::{spawn, task::JoinHandle, time::sleep};
-use tracing::{error, info, instrument, Level};
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    rngs::SmallRng,
+    Rng, RngCore, SeedableRng,
+};
+use tokio::{
+    select, spawn,
+    task::JoinHandle,
+    time::{sleep, sleep_until, Instant},
+};
+use tracing::{error, info, warn};
 
-use crate::args::{Args, LoadTesterArgs, LoadTesterJobArgs, Mode};
+use crate::{
+    args::{
+        Args, ArrivalOverflow, LoadTesterArgs, LoadTesterJobArgs, MetricsArgs, Mode, Operation,
+        OperationMix, RangeOffset,
+    },
+    backend::{self, Backend, UploadedPart},
+    distribution::{KeySampler, SizeSampler},
+    key::{self, KeyTemplate},
+    metrics::{ErrorKind, MetricsSnapshot, TaskMetrics},
+    multipart::{MultipartPlanner, MultipartUpload},
+    report::Reporter,
+    retry::{ErrorBudget, RetryPolicy},
+    server::MetricsServer,
+    sink::ReadSink,
+    slo::{self, SloViolation},
+    stage::{StageController, Throttle},
+    timeout::{InFlightRequests, Timeouts, Watchdog},
+    timeseries::Sampler,
+    verify::Corruption,
+};
 
 pub struct ObjectStorageSession {
-    bucket: Bucket,
+    backend: Arc<dyn Backend>,
+    error_budget: Option<Arc<ErrorBudget>>,
+    key_sampler: Arc<KeySampler>,
+    keys: Arc<KeyTemplate>,
     load_tester: LoadTesterArgs,
     load_tester_job: LoadTesterJobArgs,
+    metrics: MetricsArgs,
+    multipart: MultipartPlanner,
+    reporter: Option<Reporter>,
+    retry: RetryPolicy,
+    size_sampler: Arc<SizeSampler>,
+    /// Waited for after the initialization, to start along with the others
+    start_barrier: Option<BoxFuture<'static, Result<()>>>,
+    started_at: Arc<OnceLock<DateTime<Utc>>>,
+    task_metrics: Vec<Arc<TaskMetrics>>,
 }
 
 impl ObjectStorageSession {
     pub async fn try_default() -> Result<Self> {
         ::dotenv::dotenv().ok();
-        let args = Args::try_parse()?;
+        let args = Args::try_parse_with_config()?;
+        Self::try_new(args).await
+    }
+
+    pub async fn try_new(args: Args) -> Result<Self> {
         args.print();
 
+        let backend = backend::try_new(&args).await?;
+        let reporter = Reporter::new(&args);
+
         let Args {
-            bucket_name,
-            bucket_create,
-            credentials,
+            config: _,
+            backend: _,
+            bucket_name: _,
+            bucket_create: _,
+            credentials: _,
+            distributed: _,
             load_tester,
             load_tester_job,
-            region,
+            metrics,
+            region: _,
         } = args;
 
-        let mut bucket = Bucket::new(
-            &bucket_name,
-            region.clone().into(),
-            credentials.clone().into(),
-        )
-        .map_err(|error| anyhow!("failed to initialize object storage bucket client: {error}"))?
-        .with_path_style();
-
-        if !check_bucket_exists(&bucket).await {
-            if bucket_create {
-                let config = BucketConfiguration::private();
-                let response = Bucket::create_with_path_style(
-                    &bucket_name,
-                    region.into(),
-                    credentials.into(),
-                    config,
-                )
-                .await
-                .map_err(|error| anyhow!("failed to create object storage bucket: {error}"))?;
-                if response.success() {
-                    bucket = response.bucket.with_path_style();
-                } else {
-                    bail!("failed to create bucket: {bucket_name}")
-                }
-            } else {
-                bail!("no such bucket: {bucket_name}")
-            }
+        if load_tester_job.arrival_rate.is_some() && !load_tester_job.stages.is_empty() {
+            bail!("load stages cannot be combined with the open-loop arrival rate")
+        }
+        if load_tester_job.delete_batch == 0 {
+            bail!("delete batch should be positive")
+        }
+        if load_tester.multipart_concurrency == 0 {
+            bail!("multipart concurrency should be positive")
+        }
+        if load_tester_job.ranges_per_object == 0 {
+            bail!("ranges per object should be positive")
+        }
+        if load_tester_job.retry_max_attempts == 0 {
+            bail!("retry max attempts should be positive")
         }
 
+        let key_sampler = KeySampler::try_new(
+            load_tester.key_distribution,
+            load_tester.step.as_u64() as usize,
+        )
+        .map(Arc::new)
+        .map_err(|error| anyhow!("failed to initialize key distribution: {error}"))?;
+        let keys = {
+            let run_id = load_tester
+                .run_id
+                .clone()
+                .unwrap_or_else(KeyTemplate::new_run_id);
+            info!("Run ID: {run_id}");
+            KeyTemplate::try_new(&load_tester, run_id, load_tester_job.total_tasks())
+                .map(Arc::new)
+                .map_err(|error| anyhow!("failed to initialize key template: {error}"))?
+        };
+        let size_sampler =
+            SizeSampler::try_new(load_tester.size, load_tester.size_distribution.as_ref())
+                .map(Arc::new)
+                .map_err(|error| {
+                    anyhow!("failed to initialize object size distribution: {error}")
+                })?;
+        let multipart = MultipartPlanner::try_new(&load_tester, size_sampler.max())
+            .map_err(|error| anyhow!("invalid multipart upload: {error}"))?;
+        let error_budget = load_tester_job
+            .error_budget
+            .map(ErrorBudget::try_new)
+            .transpose()?
+            .map(Arc::new);
+        let retry = RetryPolicy::new(&load_tester_job);
+
+        let task_metrics = (0..load_tester_job.total_tasks())
+            .map(|_| Arc::<TaskMetrics>::default())
+            .collect();
+
         Ok(Self {
-            bucket,
+            backend,
+            error_budget,
+            key_sampler,
+            keys,
             load_tester,
             load_tester_job,
+            metrics,
+            multipart,
+            reporter,
+            retry,
+            size_sampler,
+            start_barrier: None,
+            started_at: Arc::default(),
+            task_metrics,
+        })
+    }
+
+    pub fn metrics_server(&self) -> Option<MetricsServer> {
+        self.metrics.metrics_address.map(|address| MetricsServer {
+            address,
+            mode: self.load_tester_job.mode,
+            tasks: self.task_metrics.clone(),
         })
     }
 
-    pub fn spawn(self, signal: FunctionSignal) -> JoinHandle<()> {
+    /// Waits for the barrier once initialized, before starting the workload.
+    pub fn with_start_barrier(
+        mut self,
+        barrier: impl Future<Output = Result<()>> + Send + 'static,
+    ) -> Self {
+        self.start_barrier = Some(Box::pin(barrier));
+        self
+    }
+
+    /// Returns the time the workload starts at, once initialized.
+    pub fn started_at(&self) -> Arc<OnceLock<DateTime<Utc>>> {
+        self.started_at.clone()
+    }
+
+    pub fn task_metrics(&self) -> &[Arc<TaskMetrics>] {
+        &self.task_metrics
+    }
+
+    pub fn spawn(self, signal: FunctionSignal) -> JoinHandle<Result<()>> {
         spawn(self.loop_forever(signal))
     }
 
-    async fn loop_forever(self, signal: FunctionSignal) {
-        match self.try_loop_forever(signal.clone()).await {
+    async fn loop_forever(self, signal: FunctionSignal) -> Result<()> {
+        let result = self.try_loop_forever(signal.clone()).await;
+        match &result {
             Ok(()) => signal.terminate(),
+            // Not a crash, but exits with its own code
+            Err(error) if error.is::<SloViolation>() => {
+                error!("{error}");
+                signal.terminate()
+            }
             Err(error) => {
                 error!("{error}");
                 signal.terminate_on_panic()
             }
         }
+        result
     }
 
-    async fn try_loop_forever(self, signal: FunctionSignal) -> Result<()> {
+    /// Runs the load until finished, without terminating the signal.
+    pub async fn try_loop_forever(self, signal: FunctionSignal) -> Result<()> {
+        let duration = self.load_tester_job.total_duration();
+        let timeouts = Timeouts::new(&self.load_tester_job);
+        let total_tasks = self.load_tester_job.total_tasks();
+
         let Self {
-            bucket,
+            backend,
+            error_budget,
+            key_sampler,
+            keys,
             load_tester: args,
             load_tester_job:
                 LoadTesterJobArgs {
-                    duration,
+                    arrival_overflow,
+                    arrival_rate,
+                    connect_timeout: _,
+                    delete_batch,
+                    delete_refill,
+                    duration: _,
+                    error_budget: _,
+                    first_byte_timeout: _,
+                    list_delimiter,
+                    list_depth,
+                    list_page_size,
+                    max_outstanding,
+                    mix,
                     mode,
+                    no_cleanup,
                     no_progress_bar,
-                    threads_max,
+                    range_offset,
+                    range_size,
+                    ranges_per_object,
+                    request_timeout: _,
+                    retry_backoff: _,
+                    retry_backoff_max: _,
+                    retry_max_attempts: _,
+                    retry_on: _,
+                    stages,
+                    stuck_request_threshold,
+                    threads_max: _,
                 },
+            metrics: metrics_args,
+            multipart,
+            reporter,
+            retry,
+            size_sampler,
+            start_barrier,
+            started_at,
+            task_metrics: metrics,
         } = self;
 
-        let duration = duration.map(Into::into);
+        // Split the open-loop schedule evenly into the tasks
+        let arrival_rate = arrival_rate
+            .map(|rate| rate.requests_per_sec(size_sampler.mean()) / total_tasks as f64);
+        let max_outstanding = max_outstanding.div_ceil(total_tasks);
+
+        // Share a buffer large enough to slice the largest object at every offset
+        let buf: Arc<[u8]> = {
+            let len = size_sampler.max() + PAYLOAD_OFFSETS;
+            info!("Creating buffer map: {len}");
+            let mut buf = vec![0; len];
+            let mut rng = new_rng(args.seed, STREAM_BUFFER);
+            rng.fill_bytes(&mut buf);
+            buf.into()
+        };
+
         let counter = Arc::<AtomicU64>::default();
+        let counter_bytes = Arc::<AtomicU64>::default();
+        let objects = Arc::new(
+            (0..args.step.as_u64())
+                .map(|_| AtomicU64::new(SessionTask::OBJECT_ABSENT))
+                .collect::<Vec<_>>(),
+        );
+        let range_cursors = Arc::new(
+            (0..args.step.as_u64())
+                .map(|_| AtomicU64::default())
+                .collect::<Vec<_>>(),
+        );
+        let start_barrier = Arc::new(Mutex::new(start_barrier));
         let state = Arc::<AtomicU8>::default();
+        let throttle = Arc::new(Throttle::new(&stages));
 
-        let task_handler = (0..threads_max)
+        // Track the requests in flight only for the watchdog
+        let in_flight = stuck_request_threshold.map(|threshold| {
+            let in_flight = Arc::<InFlightRequests>::default();
+            let watchdog = Watchdog {
+                requests: Arc::downgrade(&in_flight),
+                threshold: threshold.into(),
+            };
+            spawn(watchdog.loop_forever(signal.clone()));
+            in_flight
+        });
+
+        if !stages.is_empty() {
+            let controller = StageController {
+                stages,
+                throttle: throttle.clone(),
+            };
+            let signal = signal.clone();
+            let state = state.clone();
+            spawn(async move {
+                if SessionTask::wait_ready(&state).await {
+                    controller.loop_forever(signal).await
+                }
+            });
+        }
+
+        let sampler = Sampler::new(&metrics_args, &metrics).map(Sampler::spawn);
+        let task_handler = (0..total_tasks)
             .map(|id| SessionTask {
                 args: args.clone(),
-                bucket: bucket.clone(),
+                arrival_overflow,
+                arrival_rate,
+                backend: backend.clone(),
+                buf: buf.clone(),
                 counter: counter.clone(),
+                counter_bytes: counter_bytes.clone(),
+                delete_batch,
+                delete_refill,
                 duration,
+                error_budget: error_budget.clone(),
                 id,
+                in_flight: in_flight.clone(),
+                key_sampler: key_sampler.clone(),
+                keys: keys.clone(),
+                list_cursor: Mutex::default(),
+                list_delimiter: list_delimiter.clone(),
+                list_depth,
+                list_page_size,
+                max_outstanding,
+                metrics: metrics[id].clone(),
+                mix,
                 mode,
+                multipart,
+                objects: objects.clone(),
+                range_cursors: range_cursors.clone(),
+                range_offset,
+                range_size: range_size.map(|size| size.as_u64() as usize),
+                ranges_per_object,
+                retry: retry.clone(),
                 signal: signal.clone(),
+                size_sampler: size_sampler.clone(),
+                start_barrier: start_barrier.clone(),
+                started_at: started_at.clone(),
                 state: state.clone(),
-                total_tasks: threads_max,
+                throttle: throttle.clone(),
+                timeouts,
+                total_tasks,
             })
             .map(|task| {
                 spawn(task.try_loop_forever())
                     .map(|result| result.map_err(Into::into).and_then(identity))
             })
             .collect::<FuturesUnordered<_>>()
-            .try_collect();
+            .try_collect::<()>()
+            .map(|result| {
+                let snapshot = MetricsSnapshot::collect(&metrics);
+                snapshot.print();
+
+                let result = result.and_then(|()| match snapshot.total_corruptions() {
+                    0 => Ok(()),
+                    count => bail!("found corrupted objects: {count}"),
+                });
 
-        if no_progress_bar {
-            task_handler.await
+                // Measure the workload only, since the initialization
+                let started_at = started_at.get().copied().unwrap_or_else(Utc::now);
+                let elapsed = (Utc::now() - started_at).to_std().unwrap_or_default();
+                let slos = slo::evaluate(&metrics_args.slo, &snapshot, elapsed);
+
+                // Keep the report of the failed runs too
+                let report = reporter
+                    .map(|reporter| reporter.write(started_at, &snapshot, &result, &slos))
+                    .transpose();
+                result?;
+                report?;
+
+                // Clean up even if the objectives have failed
+                Ok(slo::check(&slos))
+            });
+        let task_handler = async move {
+            let result = task_handler.await;
+            if let Some(sampler) = sampler {
+                let sampled = sampler.stop().await;
+                let slo = result?;
+                sampled.map(|()| slo)
+            } else {
+                result
+            }
+        };
+
+        let slo = if no_progress_bar {
+            task_handler.await?
         } else {
             let LoadTesterArgs {
                 count,
+                key_depth: _,
+                key_distribution: _,
+                key_offset: _,
+                key_prefixes: _,
+                key_template: _,
+                multipart_concurrency: _,
+                multipart_part_size: _,
                 multipart_threshold: _,
-                size,
+                run_id: _,
+                seed: _,
+                size: _,
+                size_distribution: _,
                 step: _,
+                verify: _,
             } = args;
 
             fn write_eta(state: &ProgressState, w: &mut dyn Write) {
@@ -153,7 +441,9 @@ impl ObjectStorageSession {
             }
 
             let pb = match count {
-                Some(count) => ProgressBar::new(count.as_u64() * size.as_u64()),
+                Some(count) => {
+                    ProgressBar::new((count.as_u64() as f64 * size_sampler.mean()).round() as u64)
+                }
                 None => ProgressBar::new_spinner(),
             };
 
@@ -165,12 +455,12 @@ impl ObjectStorageSession {
                 .progress_chars("#>-");
             pb.set_style(style);
 
-            while state.load(Ordering::SeqCst) != SessionTask::STATE_READE {
-                sleep(Duration::from_millis(10)).await;
-            }
+            // The failure of the initialization is left to the task handler
+            SessionTask::wait_ready(&state).await;
+            let mut task_handler = pin!(task_handler);
             loop {
                 let progressed = counter.load(Ordering::SeqCst);
-                pb.set_position(progressed * size.as_u64());
+                pb.set_position(counter_bytes.load(Ordering::SeqCst));
 
                 let is_finished = count
                     .as_ref()
@@ -180,65 +470,120 @@ impl ObjectStorageSession {
                     if is_finished {
                         pb.finish();
                     }
-                    task_handler.await?;
-                    break cleanup(bucket).await;
+                    break task_handler.await?;
                 }
 
-                sleep(Duration::from_millis(50)).await;
+                // The tasks may stop earlier, e.g. on the duration
+                select! {
+                    result = &mut task_handler => break result?,
+                    () = sleep(Duration::from_millis(50)) => {}
+                }
             }
+        };
+
+        if !no_cleanup {
+            cleanup(&*backend, &keys).await?;
         }
+        slo
     }
 }
 
 struct SessionTask {
     args: LoadTesterArgs,
-    bucket: Bucket,
+    arrival_overflow: ArrivalOverflow,
+    arrival_rate: Option<f64>,
+    backend: Arc<dyn Backend>,
+    buf: Arc<[u8]>,
     counter: Arc<AtomicU64>,
+    counter_bytes: Arc<AtomicU64>,
+    delete_batch: usize,
+    delete_refill: bool,
     duration: Option<Duration>,
+    error_budget: Option<Arc<ErrorBudget>>,
     id: usize,
+    /// Requests in flight of all the tasks, if watched
+    in_flight: Option<Arc<InFlightRequests>>,
+    key_sampler: Arc<KeySampler>,
+    keys: Arc<KeyTemplate>,
+    /// Listing to be continued with the next page
+    list_cursor: Mutex<Option<ListCursor>>,
+    list_delimiter: Option<String>,
+    list_depth: usize,
+    list_page_size: usize,
+    max_outstanding: usize,
+    metrics: Arc<TaskMetrics>,
+    mix: OperationMix,
     mode: Mode,
+    multipart: MultipartPlanner,
+    /// Sizes of the written objects
+    objects: Arc<Vec<AtomicU64>>,
+    /// Offsets of the next sequential ranges of the objects
+    range_cursors: Arc<Vec<AtomicU64>>,
+    range_offset: RangeOffset,
+    range_size: Option<usize>,
+    ranges_per_object: usize,
+    retry: RetryPolicy,
     signal: FunctionSignal,
+    size_sampler: Arc<SizeSampler>,
+    /// Taken by the task initializing the session
+    start_barrier: Arc<Mutex<Option<BoxFuture<'static, Result<()>>>>>,
+    started_at: Arc<OnceLock<DateTime<Utc>>>,
     state: Arc<AtomicU8>,
+    throttle: Arc<Throttle>,
+    timeouts: Timeouts,
     total_tasks: usize,
 }
 
 impl SessionTask {
-    const CONTENT_TYPE: &'static str = "application/octet-stream";
+    const OBJECT_ABSENT: u64 = u64::MAX;
 
     const STATE_PENDING: u8 = 0;
     const STATE_INIT: u8 = 1;
     const STATE_READE: u8 = 2;
+    const STATE_FAILED: u8 = 3;
 
     async fn try_loop_forever(self) -> Result<()> {
         let Self {
-            args:
-                LoadTesterArgs {
-                    count,
-                    multipart_threshold: _,
-                    size,
-                    step,
-                },
-            bucket: _,
+            args: _,
+            arrival_overflow,
+            arrival_rate,
+            backend: _,
+            buf: _,
             counter: _,
-            duration,
+            counter_bytes: _,
+            delete_batch: _,
+            delete_refill: _,
+            duration: _,
+            error_budget: _,
             id,
+            in_flight: _,
+            key_sampler: _,
+            keys: _,
+            list_cursor: _,
+            list_delimiter: _,
+            list_depth: _,
+            list_page_size: _,
+            max_outstanding,
+            metrics,
+            mix,
             mode,
-            signal,
+            multipart: _,
+            objects: _,
+            range_cursors: _,
+            range_offset: _,
+            range_size: _,
+            ranges_per_object: _,
+            retry: _,
+            signal: _,
+            size_sampler: _,
+            start_barrier: _,
+            started_at,
             state,
+            throttle,
+            timeouts: _,
             total_tasks,
         } = &self;
 
-        let count = count.map(|count| count.as_u64() as usize);
-        let size = size.as_u64() as usize;
-        let step = step.as_u64() as usize;
-
-        info!("Creating buffer map: {id}/{total_tasks}");
-        let mut buf = vec![0; size + step];
-        {
-            let mut rng = SmallRng::from_entropy();
-            rng.fill_bytes(&mut buf);
-        }
-
         if state
             .compare_exchange(
                 Self::STATE_PENDING,
@@ -249,57 +594,414 @@ impl SessionTask {
             .is_ok()
         {
             info!("Initializing mode: {mode:?}");
-            match mode {
-                Mode::Read => self.init_read(&buf).await?,
-                Mode::Write => self.init_write().await?,
+            if let Err(error) = self.init().await {
+                state.store(Self::STATE_FAILED, Ordering::SeqCst);
+                return Err(error);
             }
+            started_at.set(Utc::now()).ok();
             state.store(Self::STATE_READE, Ordering::SeqCst);
-        } else {
-            while state.load(Ordering::SeqCst) != Self::STATE_READE {
-                sleep(Duration::from_millis(10)).await;
-            }
+        } else if !Self::wait_ready(state).await {
+            bail!("initialization has failed on the other task")
         }
 
         info!("Starting task: {id}/{total_tasks}");
 
+        let operations = mix.weights();
+        let weights = WeightedIndex::new(operations.iter().map(|(_, weight)| *weight))?;
+        let mut rng = new_rng(self.args.seed, *id as u64);
+
         let mut index = *id;
         let instant = Instant::now();
 
-        loop {
-            if signal.is_terminating() {
-                break;
+        match arrival_rate {
+            Some(rate) => {
+                // Open loop: issue requests on schedule regardless of the responses
+                let interval = Duration::from_secs_f64(1.0 / rate);
+                let outstanding = (*max_outstanding).max(1);
+
+                let mut next = instant + interval.mul_f64(*id as f64 / *total_tasks as f64);
+                let mut requests = FuturesUnordered::new();
+
+                while !self.is_finished(index, instant) {
+                    select! {
+                        Some(result) = requests.next(), if !requests.is_empty() => {
+                            result?;
+                            continue;
+                        }
+                        () = sleep_until(next) => {}
+                    }
+
+                    // Measure latency from the intended time to avoid coordinated omission
+                    let intended = next;
+                    next += interval;
+
+                    if requests.len() >= outstanding {
+                        match arrival_overflow {
+                            ArrivalOverflow::Delay => {
+                                metrics.record_delayed();
+                                while requests.len() >= outstanding {
+                                    if let Some(result) = requests.next().await {
+                                        result?;
+                                    }
+                                }
+                            }
+                            ArrivalOverflow::Drop => {
+                                metrics.record_dropped();
+                                continue;
+                            }
+                        }
+                    }
+
+                    let batch = self.next_batch(&mut index, &operations, &weights, &mut rng);
+                    requests.push(self.request_batch(batch, intended));
+                }
+
+                while let Some(result) = requests.next().await {
+                    result?;
+                }
             }
-            if let Some(count) = count {
-                if index >= count {
-                    break;
+            None => {
+                while !self.is_finished(index, instant) {
+                    if !throttle.is_active(*id) {
+                        sleep(Duration::from_millis(10)).await;
+                        continue;
+                    }
+                    throttle.acquire().await;
+
+                    let batch = self.next_batch(&mut index, &operations, &weights, &mut rng);
+                    self.request_batch(batch, Instant::now()).await?;
                 }
             }
-            if let Some(duration) = *duration {
-                if instant.elapsed() >= duration {
-                    break;
+        }
+
+        info!("Stopped task: {id}/{total_tasks}");
+        Ok(())
+    }
+
+    fn is_finished(&self, index: usize, instant: Instant) -> bool {
+        if self.signal.is_terminating() {
+            return true;
+        }
+        if let Some(count) = self.count() {
+            if index >= count {
+                return true;
+            }
+        }
+        if let Some(duration) = self.duration {
+            if instant.elapsed() >= duration {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn count(&self) -> Option<usize> {
+        match self.args.count {
+            Some(count) => Some(count.as_u64() as usize),
+            // Stop after deleting the whole key space once
+            None if self.mode == Mode::Delete && !self.delete_refill => {
+                Some(self.args.step.as_u64() as usize)
+            }
+            None => None,
+        }
+    }
+
+    fn next_batch(
+        &self,
+        index: &mut usize,
+        operations: &[(Operation, u32)],
+        weights: &WeightedIndex<u32>,
+        rng: &mut SmallRng,
+    ) -> Vec<Request> {
+        let size = match self.mode {
+            Mode::Delete => self.delete_batch,
+            Mode::Head | Mode::List | Mode::Mixed | Mode::Read | Mode::Write => 1,
+        };
+        // Do not overshoot the count with the batch
+        let count = self.count().unwrap_or(usize::MAX);
+        (0..size)
+            .map_while(|_| {
+                (*index < count).then(|| self.next_request(index, operations, weights, rng))
+            })
+            .collect()
+    }
+
+    fn next_request(
+        &self,
+        index: &mut usize,
+        operations: &[(Operation, u32)],
+        weights: &WeightedIndex<u32>,
+        rng: &mut SmallRng,
+    ) -> Request {
+        let index = {
+            let ret = *index;
+            *index += self.total_tasks;
+            if self.mode == Mode::Delete && !self.delete_refill {
+                // Visit every key to delete the whole key space, whatever the distribution
+                ret % self.args.step.as_u64() as usize
+            } else {
+                self.key_sampler.sample(ret, rng)
+            }
+        };
+        let operation = match self.mode {
+            Mode::Mixed => match operations[weights.sample(rng)].0 {
+                // Objects removed by previous deletions should be restored first
+                operation if operation != Operation::Put && self.object_size(index).is_none() => {
+                    Operation::Put
                 }
+                operation => operation,
+            },
+            Mode::Delete => Operation::Delete,
+            Mode::Head => Operation::Head,
+            Mode::List => Operation::List,
+            Mode::Read => Operation::Get,
+            Mode::Write => Operation::Put,
+        };
+        let size = match operation {
+            Operation::Put => self.size_sampler.sample(rng),
+            Operation::Delete if self.delete_refill => self.size_sampler.sample(rng),
+            Operation::Get | Operation::Head | Operation::Delete | Operation::List => 0,
+        };
+        let ranges = match (operation, self.range_size) {
+            (Operation::Get, Some(range_size)) => self.next_ranges(index, range_size, rng),
+            _ => Vec::default(),
+        };
+        Request {
+            index,
+            operation,
+            ranges,
+            size,
+        }
+    }
+
+    fn next_ranges(
+        &self,
+        index: usize,
+        range_size: usize,
+        rng: &mut SmallRng,
+    ) -> Vec<Range<usize>> {
+        let Some(size) = self.object_size(index) else {
+            // Read the whole object instead, not to guess its size
+            self.metrics.record_range_fallback();
+            return Vec::default();
+        };
+        if size == 0 {
+            return Vec::default();
+        }
+        let range_size = range_size.clamp(1, size);
+
+        (0..self.ranges_per_object)
+            .map(|_| {
+                let start = match self.range_offset {
+                    RangeOffset::Random => rng.gen_range(0..=size - range_size),
+                    RangeOffset::Sequential => {
+                        // Rewind at the end of the object
+                        let wrap = |start: u64| {
+                            if start as usize + range_size > size {
+                                0
+                            } else {
+                                start
+                            }
+                        };
+                        let start = self.range_cursors[index]
+                            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |start| {
+                                Some(wrap(start) + range_size as u64)
+                            })
+                            .unwrap_or_default();
+                        wrap(start) as usize
+                    }
+                };
+                start..start + range_size
+            })
+            .collect()
+    }
+
+    /// Issues the requests together, deleting the objects of a batch with a single request.
+    async fn request_batch(&self, requests: Vec<Request>, intended: Instant) -> Result<()> {
+        if requests.len() > 1 && self.mode == Mode::Delete {
+            let indices = requests
+                .iter()
+                .map(|request| request.index)
+                .collect::<Vec<_>>();
+            self.call_many(&indices, intended).await?;
+
+            return requests
+                .into_iter()
+                .map(|request| self.refill(request))
+                .collect::<FuturesUnordered<_>>()
+                .try_collect()
+                .await;
+        }
+
+        requests
+            .into_iter()
+            .map(|request| self.request(request, intended))
+            .collect::<FuturesUnordered<_>>()
+            .try_collect()
+            .await
+    }
+
+    async fn request(&self, request: Request, intended: Instant) -> Result<()> {
+        self.call(&request, intended).await?;
+        if self.mode == Mode::Delete && request.operation == Operation::Delete {
+            self.refill(request).await?;
+        }
+        Ok(())
+    }
+
+    /// Rewrites the deleted object to keep the population steady, if requested.
+    async fn refill(&self, request: Request) -> Result<()> {
+        if !self.delete_refill {
+            return Ok(());
+        }
+        let request = Request {
+            operation: Operation::Put,
+            ..request
+        };
+        self.call(&request, Instant::now()).await
+    }
+
+    async fn call(&self, request: &Request, intended: Instant) -> Result<()> {
+        let Request {
+            index,
+            operation,
+            ref ranges,
+            size,
+        } = *request;
+
+        let key = || self.keys.render(index);
+        self.attempt(operation, key, 1, intended, || async {
+            match operation {
+                Operation::Get => self.read(index, ranges, true).await,
+                // Do not count the refilled objects as the progress of the deletions
+                Operation::Put => self.write(index, size, self.mode != Mode::Delete).await,
+                Operation::Head => self.head(index, true).await,
+                Operation::Delete => self.delete(index, true).await,
+                Operation::List => self.list(index, true).await,
             }
+        })
+        .await
+    }
 
-            let index = {
-                let ret = index;
-                index += *total_tasks;
-                ret % step
+    /// Deletes the objects with a single request, recording each of them.
+    async fn call_many(&self, indices: &[usize], intended: Instant) -> Result<()> {
+        let key = || {
+            format!(
+                "{first} and {more} more",
+                first = self.keys.render(indices[0]),
+                more = indices.len() - 1,
+            )
+        };
+        self.attempt(Operation::Delete, key, indices.len(), intended, || {
+            self.delete_many(indices, true)
+        })
+        .await
+    }
+
+    /// Tries the request until it succeeds or runs out of the retries,
+    /// recording its result for each of the `objects`.
+    ///
+    /// Each attempt is registered in flight with the key, leaving out the backoffs.
+    async fn attempt<K, F, Fut>(
+        &self,
+        operation: Operation,
+        key: K,
+        objects: usize,
+        intended: Instant,
+        f: F,
+    ) -> Result<()>
+    where
+        K: Fn() -> String,
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<u64>>,
+    {
+        for _ in 0..objects {
+            self.metrics.begin();
+        }
+        let mut attempt = 1;
+        let result = loop {
+            let guard = self
+                .in_flight
+                .as_ref()
+                .map(|in_flight| in_flight.begin(operation, key()));
+            let result = self.timeouts.total(f()).await;
+            drop(guard);
+            let kind = match &result {
+                Ok(_) => break result,
+                Err(error) => ErrorKind::from(error),
             };
-            match mode {
-                Mode::Read => self.read(index, true).await?,
-                Mode::Write => self.write(index, &buf, true).await?,
+
+            let backoff = self.retry.backoff(attempt, kind, &mut ::rand::thread_rng());
+            match backoff {
+                Some(backoff) if !self.signal.is_terminating() => {
+                    self.metrics.record_retry(operation, kind);
+                    sleep(backoff).await;
+                    attempt += 1;
+                }
+                _ => break result,
+            }
+        };
+
+        match result {
+            Ok(bytes) => {
+                let latency = intended.elapsed();
+                for _ in 0..objects {
+                    self.metrics.record(operation, latency, bytes);
+                    if let Some(error_budget) = &self.error_budget {
+                        error_budget.record_success();
+                    }
+                }
+                Ok(())
+            }
+            Err(error) => {
+                for _ in 0..objects {
+                    self.metrics.record_error(operation, &error);
+                }
+                if error.is::<Corruption>() {
+                    // Keep going to count all the corrupted objects
+                    Ok(())
+                } else if let Some(error_budget) = &self.error_budget {
+                    (0..objects).try_for_each(|_| error_budget.record_failure())
+                } else {
+                    Err(error)
+                }
             }
         }
+    }
 
-        info!("Stopped task: {id}/{total_tasks}");
-        Ok(())
+    /// Waits for the first task to initialize, returning whether it has succeeded.
+    async fn wait_ready(state: &AtomicU8) -> bool {
+        loop {
+            match state.load(Ordering::SeqCst) {
+                Self::STATE_READE => break true,
+                Self::STATE_FAILED => break false,
+                _ => sleep(Duration::from_millis(10)).await,
+            }
+        }
+    }
+
+    async fn init(&self) -> Result<()> {
+        match self.mode {
+            Mode::Delete | Mode::Head | Mode::List | Mode::Mixed | Mode::Read => {
+                self.init_read().await?
+            }
+            Mode::Write => self.init_write().await?,
+        }
+
+        let start_barrier = self.start_barrier.lock().unwrap().take();
+        match start_barrier {
+            Some(start_barrier) => start_barrier.await,
+            None => Ok(()),
+        }
     }
 
-    async fn init_read(&self, buf: &[u8]) -> Result<()> {
+    async fn init_read(&self) -> Result<()> {
         let step = self.args.step.as_u64() as usize;
 
+        let mut rng = new_rng(self.args.seed, STREAM_INIT);
         for index in 0..step {
-            self.write(index, buf, false).await?;
+            let size = self.size_sampler.sample(&mut rng);
+            self.write(index, size, false).await?;
         }
         Ok(())
     }
@@ -308,130 +1010,306 @@ impl SessionTask {
         Ok(())
     }
 
-    async fn read(&self, index: usize, add_counter: bool) -> Result<()> {
-        let size = self.args.size.as_u64() as usize;
+    fn object_size(&self, index: usize) -> Option<usize> {
+        match self.objects[index].load(Ordering::SeqCst) {
+            Self::OBJECT_ABSENT => None,
+            size => Some(size as usize),
+        }
+    }
+
+    fn payload(&self, key: &str, size: usize) -> &[u8] {
+        let offset = payload_offset(self.args.seed, key);
+        &self.buf[offset..offset + size]
+    }
+
+    async fn read(&self, index: usize, ranges: &[Range<usize>], add_counter: bool) -> Result<u64> {
+        let path = self.keys.render(index);
+
+        let expected = self.object_size(index);
+        let ranges = if ranges.is_empty() {
+            vec![None]
+        } else {
+            ranges.iter().cloned().map(Some).collect()
+        };
+
+        let mut bytes = 0;
+        for range in ranges {
+            let offset = range.as_ref().map(|range| range.start).unwrap_or_default();
+            let first_byte = OnceLock::new();
+            let mut sink = ReadSink::new(
+                if self.args.verify {
+                    self.buf
+                        .get(payload_offset(self.args.seed, &path) + offset..)
+                } else {
+                    None
+                },
+                &first_byte,
+            );
 
-        let path = get_s3_path(index);
+            let instant = Instant::now();
+            let get = self.backend.get(
+                &path,
+                range
+                    .as_ref()
+                    .map(|range| range.start as u64..range.end as u64),
+                &mut sink,
+            );
+            match self.timeouts.first_byte(get, &first_byte).await {
+                Ok(()) => (),
+                // The object may have been rewritten smaller than the range, e.g. in the mixed mode
+                Err(error)
+                    if backend::is_range_not_satisfiable(&error)
+                        && self.object_size(index).is_some_and(|size| size <= offset) =>
+                {
+                    continue
+                }
+                Err(error) => return Err(error),
+            }
+            if let Some(first_byte) = sink.first_byte() {
+                self.metrics
+                    .record_ttfb(Operation::Get, first_byte - instant);
+            }
 
-        let response = self.bucket.get_object(&path).await?;
-        // assert_eq!(response.bytes().len(), size);
-        drop(response);
+            self.verify(&path, index, expected, range, &sink)?;
+            bytes += sink.len() as u64;
+        }
 
         if add_counter {
             self.counter.fetch_add(1, Ordering::SeqCst);
+            self.counter_bytes.fetch_add(bytes, Ordering::SeqCst);
         }
-        Ok(())
+        Ok(bytes)
     }
 
-    async fn write(&self, index: usize, buf: &[u8], add_counter: bool) -> Result<()> {
-        let multipart_minimal = LoadTesterArgs::minimal_multipart_threshold().as_u64() as usize;
-        let multipart_threshold = self.args.multipart_threshold.as_u64() as usize;
-        let size = self.args.size.as_u64() as usize;
-        let use_multipart = size > multipart_threshold;
-
-        let path = get_s3_path(index);
-
-        let data = &buf[index..index + size];
-        if use_multipart {
-            let InitiateMultipartUploadResponse { upload_id, .. } = self
-                .bucket
-                .initiate_multipart_upload(&path, Self::CONTENT_TYPE)
-                .await?;
-
-            let mut chunks = vec![];
-            {
-                let mut pos = 0;
-                let len = data.len();
-                while pos < len {
-                    let pos_next = pos + multipart_threshold;
-                    let remaining = len - pos_next;
-
-                    let pos_next = if remaining >= multipart_minimal {
-                        pos_next
-                    } else {
-                        len
-                    };
-
-                    let chunk = &data[pos..pos_next];
-                    chunks.push(chunk);
-                    pos = pos_next;
+    fn verify(
+        &self,
+        path: &str,
+        index: usize,
+        expected: Option<usize>,
+        range: Option<Range<usize>>,
+        sink: &ReadSink,
+    ) -> Result<()> {
+        if !self.args.verify {
+            return Ok(());
+        }
+
+        let check = |size: usize| {
+            sink.check(match &range {
+                Some(range) => range.end.min(size) - range.start.min(size),
+                None => size,
+            })
+        };
+        match expected.map(check) {
+            Some(Err(corruption)) => match self.object_size(index) {
+                // The object may have been overwritten while reading
+                Some(size) if Some(size) != expected && check(size).is_ok() => Ok(()),
+                _ => {
+                    error!("{corruption}: {path}");
+                    bail!(corruption)
                 }
+            },
+            Some(Ok(())) => Ok(()),
+            // Written by the others, so only the content is known
+            None => {
+                self.metrics.record_unverified();
+                sink.check(sink.len()).map_err(|corruption| {
+                    error!("{corruption}: {path}");
+                    corruption.into()
+                })
             }
+        }
+    }
 
-            let mut parts = vec![];
-            for (part_number, reader) in chunks.iter_mut().enumerate() {
-                let part_number = (part_number + 1).try_into()?;
-
-                let part = self
-                    .bucket
-                    .put_multipart_stream(
-                        reader,
-                        &path,
-                        part_number,
-                        &upload_id,
-                        Self::CONTENT_TYPE,
-                    )
-                    .await?;
-                parts.push(part);
-            }
+    async fn write(&self, index: usize, size: usize, add_counter: bool) -> Result<u64> {
+        let path = self.keys.render(index);
+
+        let data = self.payload(&path, size);
+        if self.multipart.is_multipart(size) {
+            // Do not leave the uploaded parts behind, even if cancelled by the request timeout
+            let upload = MultipartUpload::try_create(self.backend.clone(), &path).await?;
 
-            self.bucket
-                .complete_multipart_upload(&path, &upload_id, parts)
-                .await?;
+            // Upload the parts concurrently, keeping their order
+            let uploads = self
+                .multipart
+                .plan(size)
+                .into_iter()
+                .enumerate()
+                .map(|(part_number, range)| {
+                    self.upload_part(&path, upload.upload_id(), part_number + 1, &data[range])
+                })
+                .collect::<Vec<_>>();
+            let parts = stream::iter(uploads)
+                .buffered(self.args.multipart_concurrency)
+                .try_collect()
+                .await;
+            let parts = match parts {
+                Ok(parts) => parts,
+                Err(error) => {
+                    upload.abort().await;
+                    return Err(error);
+                }
+            };
+            upload.complete(parts).await?;
         } else {
-            let mut reader = data;
-            self.bucket.put_object_stream(&mut reader, &path).await?;
+            self.backend.put(&path, data).await?;
         }
+        self.objects[index].store(size as u64, Ordering::SeqCst);
 
         if add_counter {
             self.counter.fetch_add(1, Ordering::SeqCst);
+            self.counter_bytes.fetch_add(size as u64, Ordering::SeqCst);
         }
-        Ok(())
+        Ok(size as u64)
     }
-}
 
-async fn check_bucket_exists(bucket: &Bucket) -> bool {
-    match try_check_bucket_exists(bucket).await {
-        Ok(_) => true,
-        Err(error) => false,
+    async fn upload_part(
+        &self,
+        path: &str,
+        upload_id: &str,
+        part_number: usize,
+        data: &[u8],
+    ) -> Result<UploadedPart> {
+        self.backend
+            .upload_part(path, upload_id, part_number.try_into()?, data)
+            .await
+    }
+
+    async fn head(&self, index: usize, add_counter: bool) -> Result<u64> {
+        let path = self.keys.render(index);
+
+        self.backend.head(&path).await?;
+
+        if add_counter {
+            self.counter.fetch_add(1, Ordering::SeqCst);
+        }
+        Ok(0)
+    }
+
+    async fn delete(&self, index: usize, add_counter: bool) -> Result<u64> {
+        let path = self.keys.render(index);
+
+        self.backend.delete(&path).await?;
+        self.objects[index].store(Self::OBJECT_ABSENT, Ordering::SeqCst);
+
+        if add_counter {
+            self.counter.fetch_add(1, Ordering::SeqCst);
+        }
+        Ok(0)
+    }
+
+    async fn delete_many(&self, indices: &[usize], add_counter: bool) -> Result<u64> {
+        let paths = indices
+            .iter()
+            .map(|index| self.keys.render(*index))
+            .collect::<Vec<_>>();
+
+        self.backend.delete_many(&paths).await?;
+        for index in indices {
+            self.objects[*index].store(Self::OBJECT_ABSENT, Ordering::SeqCst);
+        }
+
+        if add_counter {
+            self.counter
+                .fetch_add(indices.len() as u64, Ordering::SeqCst);
+        }
+        Ok(0)
+    }
+
+    async fn list(&self, index: usize, add_counter: bool) -> Result<u64> {
+        // Continue the previous listing if not finished yet
+        let cursor = self.list_cursor.lock().unwrap().take();
+        let (prefix, token) = match cursor {
+            Some(ListCursor { prefix, token }) => (prefix, Some(token)),
+            None => (self.keys.render_dirs(index, self.list_depth), None),
+        };
+
+        let page = self
+            .backend
+            .list(
+                &prefix,
+                self.list_delimiter.as_deref(),
+                token,
+                self.list_page_size,
+            )
+            .await?;
+        if let Some(token) = page.next_token {
+            *self.list_cursor.lock().unwrap() = Some(ListCursor { prefix, token });
+        }
+
+        if add_counter {
+            self.counter.fetch_add(1, Ordering::SeqCst);
+        }
+        Ok(0)
     }
 }
 
-#[instrument(skip_all, err(level = Level::ERROR))]
-async fn try_check_bucket_exists(bucket: &Bucket) -> Result<()> {
-    const TEST_FILE: &'static str = "/_sos_bucket_test";
+struct Request {
+    index: usize,
+    operation: Operation,
+    /// Byte ranges to be read, or the whole object if empty
+    ranges: Vec<Range<usize>>,
+    /// Size of the object to be written
+    size: usize,
+}
 
-    bucket.put_object(TEST_FILE, TEST_FILE.as_bytes()).await?;
-    bucket.delete_object(TEST_FILE).await.ok();
-    Ok(())
+struct ListCursor {
+    prefix: String,
+    token: String,
 }
 
-async fn cleanup(bucket: Bucket) -> Result<()> {
+pub async fn cleanup(backend: &dyn Backend, keys: &KeyTemplate) -> Result<()> {
     info!("Cleaning up...");
 
-    let delimeter = "/".into();
-    let prefix = "/sample/".into();
-    let files = bucket
-        .list(prefix, Some(delimeter))
-        .await
-        .map_err(|error| anyhow!("failed to validate bucket files: {error}"))?;
-
-    files
-        .into_iter()
-        .map(|file| file.name)
-        .map(|path| bucket.delete_object(path).map_ok(|_| ()))
-        .collect::<FuturesUnordered<_>>()
-        .try_collect()
-        .map_err(|error| anyhow!("failed to cleanup bucket: {error}"))
-        .await
+    for prefix in keys.prefixes() {
+        if prefix.is_empty() {
+            warn!("Skipping cleanup: the key template has no constant prefix");
+            return Ok(());
+        }
 
-    // bucket
-    //     .delete_object("/sample/")
-    //     .map_ok(|_| ())
-    //     .map_err(|error| anyhow!("failed to cleanup bucket: {error}"))
-    //     .await
+        let mut token = None;
+        loop {
+            let page = backend
+                .list(&prefix, None, token, 1000)
+                .await
+                .map_err(|error| anyhow!("failed to validate bucket files: {error}"))?;
+
+            backend
+                .delete_many(&page.keys)
+                .await
+                .map_err(|error| anyhow!("failed to cleanup bucket: {error}"))?;
+
+            match page.next_token {
+                Some(next_token) => token = Some(next_token),
+                None => break,
+            }
+        }
+    }
+    Ok(())
 }
 
-fn get_s3_path(index: usize) -> String {
-    format!("/sample/{index:06}.bin")
+/// Number of the offsets in the shared buffer to start the payloads at
+const PAYLOAD_OFFSETS: usize = 1 << 16;
+
+/// Returns the offset of the payload of the key in the shared buffer.
+///
+/// The payloads depend only on the seed, the key and the size,
+/// so that any reader of the same seed can verify any object.
+fn payload_offset(seed: Option<u64>, key: &str) -> usize {
+    // FNV-1a, stable across the builds unlike the hashers of std
+    let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325, |hash: u64, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    (key::hash(hash ^ seed.unwrap_or_default()) % PAYLOAD_OFFSETS as u64) as usize
+}
+
+// Streams of the random generators besides the tasks
+const STREAM_BUFFER: u64 = u64::MAX;
+const STREAM_INIT: u64 = u64::MAX - 1;
+
+fn new_rng(seed: Option<u64>, stream: u64) -> SmallRng {
+    match seed {
+        // Mix them not to overlap the streams of the adjacent seeds
+        Some(seed) => SmallRng::seed_from_u64(key::hash(key::hash(seed) ^ stream)),
+        None => SmallRng::from_entropy(),
+    }
 }
@@ -0,0 +1,85 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::OnceLock,
+    task::{Context, Poll},
+};
+
+use tokio::{io::AsyncWrite, time::Instant};
+
+use crate::verify::Corruption;
+
+/// Consumes a streamed object body, discarding the chunks as they arrive.
+pub struct ReadSink<'a> {
+    /// Payload expected from the start of the body, if verifying
+    expected: Option<&'a [u8]>,
+    /// Shared to watch the first byte while streaming
+    first_byte: &'a OnceLock<Instant>,
+    len: usize,
+    mismatch: bool,
+}
+
+impl<'a> ReadSink<'a> {
+    pub const fn new(expected: Option<&'a [u8]>, first_byte: &'a OnceLock<Instant>) -> Self {
+        Self {
+            expected,
+            first_byte,
+            len: 0,
+            mismatch: false,
+        }
+    }
+
+    pub fn first_byte(&self) -> Option<Instant> {
+        self.first_byte.get().copied()
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Checks the consumed body against the expected payload of `len` bytes.
+    pub fn check(&self, len: usize) -> Result<(), Corruption> {
+        if self.mismatch {
+            Err(Corruption::Mismatch)
+        } else {
+            Corruption::check_len(len, self.len)
+        }
+    }
+}
+
+impl AsyncWrite for ReadSink<'_> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        this.first_byte.get_or_init(Instant::now);
+
+        if let Some(expected) = this.expected {
+            // The bytes beyond the expected payload are caught by the length check
+            let start = this.len.min(expected.len());
+            let end = (this.len + buf.len()).min(expected.len());
+            if buf[..end - start] != expected[start..end] {
+                this.mismatch = true;
+            }
+        }
+        this.len += buf.len();
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,231 @@
+use std::{error::Error, fmt, time::Duration};
+
+use anyhow::Result;
+use byte_unit::{Byte, UnitType};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::{
+    args::{Slo, SloObjective},
+    metrics::{MetricsSnapshot, OperationMetrics},
+};
+
+/// Failure of the service level objectives, distinguished from the failed runs.
+#[derive(Debug)]
+pub struct SloViolation {
+    pub failed: usize,
+    pub total: usize,
+}
+
+impl SloViolation {
+    /// Exit code of the process, besides `1` of the failed runs
+    pub const EXIT_CODE: i32 = 2;
+}
+
+impl fmt::Display for SloViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { failed, total } = self;
+        write!(f, "{failed} of {total} SLOs failed")
+    }
+}
+
+impl Error for SloViolation {}
+
+/// Result of an objective on the metrics of the run.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SloResult {
+    /// Actual value to display, or `None` if there has been no requests to measure
+    pub actual: Option<String>,
+    pub passed: bool,
+    pub slo: String,
+}
+
+/// Evaluates the objectives on the metrics of the run.
+pub fn evaluate(slos: &[Slo], snapshot: &MetricsSnapshot, elapsed: Duration) -> Vec<SloResult> {
+    slos.iter()
+        .map(|slo| {
+            let actual = measure(slo, snapshot, elapsed);
+            SloResult {
+                passed: actual
+                    .as_ref()
+                    .is_some_and(|(actual, threshold, _)| slo.comparison.test(*actual, *threshold)),
+                actual: actual.map(|(_, _, display)| display),
+                slo: slo.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Prints a pass/fail table of the results, failing if any has failed.
+pub fn check(results: &[SloResult]) -> Result<()> {
+    if results.is_empty() {
+        return Ok(());
+    }
+
+    info!("SLOs:");
+    let mut failed = 0;
+    for SloResult {
+        actual,
+        passed,
+        slo,
+    } in results
+    {
+        let actual = actual.as_deref().unwrap_or("no requests");
+        if *passed {
+            info!("[PASS] {slo} | actual: {actual}");
+        } else {
+            error!("[FAIL] {slo} | actual: {actual}");
+            failed += 1;
+        }
+    }
+
+    match failed {
+        0 => Ok(()),
+        failed => Err(SloViolation {
+            failed,
+            total: results.len(),
+        }
+        .into()),
+    }
+}
+
+/// Returns the actual value, the threshold and the actual value to display,
+/// or `None` if there has been no requests to measure.
+fn measure(slo: &Slo, snapshot: &MetricsSnapshot, elapsed: Duration) -> Option<(f64, f64, String)> {
+    let mut metrics = OperationMetrics::default();
+    for (operation, other) in &snapshot.operations {
+        if slo
+            .operation
+            .map_or(true, |expected| expected == *operation)
+        {
+            metrics.merge(other);
+        }
+    }
+
+    let count = metrics.latency.len();
+    let errors = metrics.total_errors();
+    if count + errors == 0 {
+        return None;
+    }
+    let elapsed = elapsed.as_secs_f64();
+    let per_sec = |value: u64| {
+        if elapsed > 0.0 {
+            value as f64 / elapsed
+        } else {
+            0.0
+        }
+    };
+
+    Some(match &slo.objective {
+        SloObjective::ErrorRate(threshold) => {
+            let actual = 100.0 * errors as f64 / (count + errors) as f64;
+            (actual, *threshold, format!("{actual:.3}%"))
+        }
+        SloObjective::Latency {
+            latency,
+            percentile,
+        } => {
+            let threshold = Duration::from(*latency).as_micros() as f64;
+            let actual = metrics.quantile(percentile / 100.0);
+            (actual.as_micros() as f64, threshold, format!("{actual:?}"))
+        }
+        SloObjective::Rate(threshold) => {
+            let actual = per_sec(count);
+            (actual, *threshold, format!("{actual:.1}/s"))
+        }
+        SloObjective::Throughput(threshold) => {
+            let actual = per_sec(metrics.bytes);
+            let display = match Byte::from_f64(actual)
+                .map(|byte| byte.get_appropriate_unit(UnitType::Decimal))
+            {
+                Some(byte) => format!("{byte:.1}/s"),
+                None => format!("{actual}B/s"),
+            };
+            (actual, threshold.as_u64() as f64, display)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::*;
+    use crate::{
+        args::{Comparison, Operation},
+        metrics::TaskMetrics,
+    };
+
+    fn snapshot() -> MetricsSnapshot {
+        let task = TaskMetrics::default();
+        for latency in 1..=100 {
+            task.begin();
+            task.record(Operation::Get, Duration::from_millis(latency), 1_000_000);
+        }
+        task.begin();
+        task.record(Operation::Put, Duration::from_millis(10), 1_000_000);
+        task.begin();
+        task.record_error(Operation::Put, &anyhow!("unknown"));
+        task.snapshot()
+    }
+
+    fn slos(slos: &[&str]) -> Vec<Slo> {
+        slos.iter().map(|slo| slo.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn slo_parse_roundtrip() {
+        let slo: Slo = "p99.9(get) <= 50ms".parse().unwrap();
+        assert_eq!(slo.comparison, Comparison::LessOrEqual);
+        assert_eq!(slo.operation, Some(Operation::Get));
+        assert_eq!(slo.to_string().parse::<Slo>().unwrap(), slo);
+
+        for slo in [
+            "throughput(put) > 2GB/s",
+            "rate >= 1000/s",
+            "error_rate < 0.1%",
+        ] {
+            let parsed: Slo = slo.parse().unwrap();
+            assert_eq!(parsed.to_string().parse::<Slo>().unwrap(), parsed);
+        }
+
+        for slo in [
+            "p99(get) 50ms",
+            "p0 < 1s",
+            "p99(copy) < 1s",
+            "error_rate < 0.1",
+        ] {
+            assert!(slo.parse::<Slo>().is_err(), "{slo}");
+        }
+    }
+
+    #[test]
+    fn slo_pass() {
+        let slos = slos(&[
+            "p50(get) < 60ms",
+            "p99 >= 90ms",
+            "throughput(get) > 50MB/s",
+            "rate(put) >= 0.5/s",
+            "error_rate(get) < 0.1%",
+        ]);
+        check(&evaluate(&slos, &snapshot(), Duration::from_secs(1))).unwrap();
+    }
+
+    #[test]
+    fn slo_fail() {
+        let slos = slos(&[
+            "p99(get) < 50ms",
+            "error_rate(put) < 10%",
+            "rate(head) > 0/s",
+            "throughput > 1MB/s",
+        ]);
+        let results = evaluate(&slos, &snapshot(), Duration::from_secs(1));
+        assert_eq!(results[2].actual, None);
+
+        let error = check(&results).unwrap_err();
+        let violation = error.downcast_ref::<SloViolation>().unwrap();
+        assert_eq!(violation.failed, 3);
+        assert_eq!(violation.total, 4);
+    }
+}
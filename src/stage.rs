@@ -0,0 +1,201 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use ark_core::signal::FunctionSignal;
+use tokio::time::{sleep, sleep_until, Instant};
+use tracing::info;
+
+use crate::args::{Stage, StageTarget};
+
+/// Limits the number of active tasks and the request rate of a session.
+pub struct Throttle {
+    concurrency: AtomicUsize,
+    last: Mutex<Option<Instant>>,
+    /// Requests per second, stored as `f64` bits
+    rate: AtomicU64,
+}
+
+impl Throttle {
+    const INTERVAL_POLL: Duration = Duration::from_millis(10);
+
+    pub fn new(stages: &[Stage]) -> Self {
+        let throttle = Self {
+            concurrency: AtomicUsize::new(usize::MAX),
+            last: Mutex::default(),
+            rate: AtomicU64::new(f64::INFINITY.to_bits()),
+        };
+        if let Some(target) = target_at(stages, Duration::ZERO) {
+            throttle.set(target);
+        }
+        throttle
+    }
+
+    pub fn is_active(&self, id: usize) -> bool {
+        id < self.concurrency.load(Ordering::SeqCst)
+    }
+
+    fn rate(&self) -> f64 {
+        f64::from_bits(self.rate.load(Ordering::SeqCst))
+    }
+
+    fn set(&self, target: StageTarget) {
+        let (concurrency, rate) = match target {
+            StageTarget::Concurrency(concurrency) => (concurrency, f64::INFINITY),
+            StageTarget::Rate(rate) => (usize::MAX, rate),
+        };
+        self.concurrency.store(concurrency, Ordering::SeqCst);
+        self.rate.store(rate.to_bits(), Ordering::SeqCst);
+    }
+
+    /// Waits until the next request is allowed by the current rate.
+    pub async fn acquire(&self) {
+        loop {
+            let rate = self.rate();
+            if rate.is_infinite() {
+                break;
+            }
+            if rate <= 0.0 {
+                sleep(Self::INTERVAL_POLL).await;
+                continue;
+            }
+
+            let interval = Duration::from_secs_f64(1.0 / rate);
+            let now = Instant::now();
+            let due = {
+                let mut last = self.last.lock().unwrap();
+                let due = last.map(|last| last + interval).unwrap_or(now);
+                if due <= now {
+                    // Do not burst to catch up the idle time
+                    *last = Some(if due + interval < now { now } else { due });
+                    break;
+                }
+                due
+            };
+
+            // Re-evaluate periodically so that the rate changes apply quickly
+            sleep_until(due.min(now + Self::INTERVAL_POLL)).await;
+        }
+    }
+}
+
+pub struct StageController {
+    pub stages: Vec<Stage>,
+    pub throttle: Arc<Throttle>,
+}
+
+impl StageController {
+    const INTERVAL_UPDATE: Duration = Duration::from_millis(100);
+
+    pub async fn loop_forever(self, signal: FunctionSignal) {
+        let Self { stages, throttle } = self;
+
+        let instant = Instant::now();
+        let mut current = None;
+
+        while !signal.is_terminating() {
+            let elapsed = instant.elapsed();
+            let index = stage_index_at(&stages, elapsed);
+            if index != current {
+                if let Some(index) = index {
+                    let stage = &stages[index];
+                    info!("Entering stage: #{index} ({stage})");
+                }
+                current = index;
+            }
+
+            match target_at(&stages, elapsed) {
+                Some(target) => throttle.set(target),
+                None => break,
+            }
+            sleep(Self::INTERVAL_UPDATE).await;
+        }
+    }
+}
+
+fn stage_index_at(stages: &[Stage], elapsed: Duration) -> Option<usize> {
+    let mut end = Duration::ZERO;
+    stages.iter().position(|stage| {
+        end += stage.duration();
+        elapsed < end
+    })
+}
+
+/// Interpolates the target between the previous and the current stages.
+fn target_at(stages: &[Stage], elapsed: Duration) -> Option<StageTarget> {
+    let index = stage_index_at(stages, elapsed)?;
+    let start: Duration = stages[..index].iter().map(Stage::duration).sum();
+    let stage = &stages[index];
+
+    let progress = (elapsed - start).as_secs_f64() / stage.duration().as_secs_f64();
+    let lerp = |from: f64, to: f64| from + (to - from) * progress;
+
+    let previous = match index {
+        0 => None,
+        index => Some(stages[index - 1].target),
+    };
+    Some(match (previous, stage.target) {
+        (None, StageTarget::Concurrency(to)) => {
+            StageTarget::Concurrency(lerp(0.0, to as f64).round() as usize)
+        }
+        (Some(StageTarget::Concurrency(from)), StageTarget::Concurrency(to)) => {
+            StageTarget::Concurrency(lerp(from as f64, to as f64).round() as usize)
+        }
+        (None, StageTarget::Rate(to)) => StageTarget::Rate(lerp(0.0, to)),
+        (Some(StageTarget::Rate(from)), StageTarget::Rate(to)) => StageTarget::Rate(lerp(from, to)),
+        // Jump directly to the new target if the kind has been changed
+        (Some(_), target) => target,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stages(stages: &[&str]) -> Vec<Stage> {
+        stages.iter().map(|stage| stage.parse().unwrap()).collect()
+    }
+
+    fn target(stages: &[Stage], millis: u64) -> Option<StageTarget> {
+        target_at(stages, Duration::from_millis(millis))
+    }
+
+    #[test]
+    fn stage_boundaries() {
+        let stages = stages(&["10s:10", "10s:10", "10s:20/s"]);
+        assert_eq!(stage_index_at(&stages, Duration::ZERO), Some(0));
+        assert_eq!(stage_index_at(&stages, Duration::from_secs(10)), Some(1));
+        assert_eq!(stage_index_at(&stages, Duration::from_secs(30)), None);
+
+        // Ramp up from zero, and hold the same target
+        assert_eq!(target(&stages, 0), Some(StageTarget::Concurrency(0)));
+        assert_eq!(target(&stages, 10_000), Some(StageTarget::Concurrency(10)));
+        assert_eq!(target(&stages, 19_999), Some(StageTarget::Concurrency(10)));
+
+        // Jump to the target of the other kind at once
+        assert_eq!(target(&stages, 20_000), Some(StageTarget::Rate(20.0)));
+        assert_eq!(target(&stages, 29_999), Some(StageTarget::Rate(20.0)));
+        assert_eq!(target(&stages, 30_000), None);
+    }
+
+    #[test]
+    fn stage_linear_ramp() {
+        let stages = stages(&["4s:100/s", "4s:20/s", "10s:20"]);
+        assert_eq!(target(&stages, 1_000), Some(StageTarget::Rate(25.0)));
+        assert_eq!(target(&stages, 2_000), Some(StageTarget::Rate(50.0)));
+        assert_eq!(target(&stages, 5_000), Some(StageTarget::Rate(80.0)));
+        assert_eq!(target(&stages, 7_000), Some(StageTarget::Rate(40.0)));
+
+        assert_eq!(target(&stages, 8_000), Some(StageTarget::Concurrency(20)));
+
+        // Round the ramping concurrency
+        let stages = self::stages(&["10s:20", "4s:10"]);
+        assert_eq!(target(&stages, 2_500), Some(StageTarget::Concurrency(5)));
+        assert_eq!(target(&stages, 5_000), Some(StageTarget::Concurrency(10)));
+        assert_eq!(target(&stages, 11_000), Some(StageTarget::Concurrency(18)));
+    }
+}
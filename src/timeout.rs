@@ -0,0 +1,286 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt,
+    future::Future,
+    pin::pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, OnceLock, Weak,
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+use ark_core::signal::FunctionSignal;
+use tokio::{
+    select,
+    time::{sleep, timeout, Instant},
+};
+use tracing::warn;
+
+use crate::args::{LoadTesterJobArgs, Operation};
+
+/// Request cancelled for taking too long.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RequestTimeout {
+    /// No byte of the body has arrived in time
+    FirstByte(Duration),
+    /// The whole request has not finished in time
+    Total(Duration),
+}
+
+impl fmt::Display for RequestTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FirstByte(limit) => write!(f, "timed out waiting for the first byte: {limit:?}"),
+            Self::Total(limit) => write!(f, "timed out waiting for the request: {limit:?}"),
+        }
+    }
+}
+
+impl Error for RequestTimeout {}
+
+/// Time limits of each request, besides the connect timeout of the backend.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Timeouts {
+    first_byte: Option<Duration>,
+    total: Option<Duration>,
+}
+
+impl Timeouts {
+    pub fn new(args: &LoadTesterJobArgs) -> Self {
+        Self {
+            first_byte: args.first_byte_timeout.map(Into::into),
+            total: args.request_timeout.map(Into::into),
+        }
+    }
+
+    /// Fails the read unless its first byte, recorded by the sink, arrives in time.
+    pub async fn first_byte<T>(
+        &self,
+        future: impl Future<Output = Result<T>>,
+        first_byte: &OnceLock<Instant>,
+    ) -> Result<T> {
+        let Some(limit) = self.first_byte else {
+            return future.await;
+        };
+
+        let mut future = pin!(future);
+        select! {
+            result = &mut future => result,
+            () = sleep(limit) => match first_byte.get() {
+                Some(_) => future.await,
+                None => Err(RequestTimeout::FirstByte(limit).into()),
+            },
+        }
+    }
+
+    pub async fn total<T>(&self, future: impl Future<Output = Result<T>>) -> Result<T> {
+        match self.total {
+            Some(limit) => timeout(limit, future)
+                .await
+                .unwrap_or_else(|_| Err(RequestTimeout::Total(limit).into())),
+            None => future.await,
+        }
+    }
+}
+
+/// Registry of the requests in flight, to find the stuck ones.
+///
+/// The requests are spread over the shards not to contend on a single lock.
+#[derive(Debug, Default)]
+pub struct InFlightRequests {
+    next_id: AtomicU64,
+    shards: [Mutex<BTreeMap<u64, InFlightRequest>>; InFlightRequests::SHARDS],
+}
+
+impl InFlightRequests {
+    const SHARDS: usize = 16;
+
+    pub fn begin(&self, operation: Operation, key: String) -> InFlightGuard<'_> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let request = InFlightRequest {
+            key,
+            operation,
+            started: Instant::now(),
+        };
+        self.shard(id).lock().unwrap().insert(id, request);
+        InFlightGuard { id, requests: self }
+    }
+
+    fn shard(&self, id: u64) -> &Mutex<BTreeMap<u64, InFlightRequest>> {
+        &self.shards[id as usize % Self::SHARDS]
+    }
+
+    /// Returns the requests in flight for longer than the threshold, the oldest first.
+    fn stuck(&self, threshold: Duration) -> Vec<(Operation, String, Duration)> {
+        let mut stuck = self
+            .shards
+            .iter()
+            .flat_map(|shard| {
+                shard
+                    .lock()
+                    .unwrap()
+                    .values()
+                    .map(|request| {
+                        let elapsed = request.started.elapsed();
+                        (request.operation, request.key.clone(), elapsed)
+                    })
+                    .filter(|(_, _, elapsed)| *elapsed >= threshold)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        stuck.sort_by(|(_, _, a), (_, _, b)| b.cmp(a));
+        stuck
+    }
+}
+
+#[derive(Debug)]
+struct InFlightRequest {
+    key: String,
+    operation: Operation,
+    started: Instant,
+}
+
+/// Unregisters the request in flight on drop.
+pub struct InFlightGuard<'a> {
+    id: u64,
+    requests: &'a InFlightRequests,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.requests
+            .shard(self.id)
+            .lock()
+            .unwrap()
+            .remove(&self.id);
+    }
+}
+
+/// Warns the requests in flight for too long, until the registry is dropped.
+pub struct Watchdog {
+    pub requests: Weak<InFlightRequests>,
+    pub threshold: Duration,
+}
+
+impl Watchdog {
+    const INTERVAL_MAX: Duration = Duration::from_secs(10);
+
+    /// Maximum number of the stuck keys to log at once
+    const KEYS_MAX: usize = 16;
+
+    pub async fn loop_forever(self, signal: FunctionSignal) {
+        let Self {
+            requests,
+            threshold,
+        } = self;
+
+        let interval = threshold.min(Self::INTERVAL_MAX);
+        while !signal.is_terminating() {
+            sleep(interval).await;
+
+            let Some(requests) = requests.upgrade() else {
+                break;
+            };
+            let stuck = requests.stuck(threshold);
+            if stuck.is_empty() {
+                continue;
+            }
+
+            let mut keys = stuck
+                .iter()
+                .take(Self::KEYS_MAX)
+                .map(|(operation, key, elapsed)| format!("{operation} {key} ({elapsed:.1?})"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            if stuck.len() > Self::KEYS_MAX {
+                keys.push_str(&format!(", and {} more", stuck.len() - Self::KEYS_MAX));
+            }
+            warn!(
+                "Stuck requests in flight for more than {threshold:?}: {count}: {keys}",
+                count = stuck.len(),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMIT: Duration = Duration::from_millis(20);
+
+    fn timeouts() -> Timeouts {
+        Timeouts {
+            first_byte: Some(LIMIT),
+            total: Some(LIMIT * 4),
+        }
+    }
+
+    #[tokio::test]
+    async fn first_byte_timeout() {
+        let first_byte = OnceLock::new();
+        let error = timeouts()
+            .first_byte(
+                async {
+                    sleep(LIMIT * 2).await;
+                    Ok(())
+                },
+                &first_byte,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref(),
+            Some(&RequestTimeout::FirstByte(LIMIT)),
+        );
+
+        // Slow bodies are fine once started
+        timeouts()
+            .first_byte(
+                async {
+                    first_byte.get_or_init(Instant::now);
+                    sleep(LIMIT * 2).await;
+                    Ok(())
+                },
+                &first_byte,
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn total_timeout() {
+        let error = timeouts()
+            .total(async {
+                sleep(LIMIT * 8).await;
+                Ok(())
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref(),
+            Some(&RequestTimeout::Total(LIMIT * 4))
+        );
+    }
+
+    #[test]
+    fn stuck_requests() {
+        let requests = InFlightRequests::default();
+        let guard = requests.begin(Operation::Get, "/a".into());
+        std::thread::sleep(Duration::from_millis(1));
+        let other = requests.begin(Operation::Put, "/b".into());
+        assert!(requests.stuck(Duration::from_secs(60)).is_empty());
+
+        let stuck = requests.stuck(Duration::ZERO);
+        assert_eq!(stuck.len(), 2);
+        assert_eq!(stuck[0].1, "/a");
+        assert_eq!(stuck[1].1, "/b");
+
+        drop(guard);
+        drop(other);
+        assert!(requests.stuck(Duration::ZERO).is_empty());
+    }
+}
@@ -0,0 +1,249 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    select, spawn,
+    sync::oneshot,
+    task::JoinHandle,
+    time::{sleep_until, Instant},
+};
+
+use crate::{
+    args::{MetricsArgs, Operation},
+    metrics::{MetricsSnapshot, OperationMetrics, TaskMetrics},
+};
+
+/// Appends the metrics of each interval to a file during the run.
+pub struct Sampler {
+    interval: Duration,
+    path: PathBuf,
+    tasks: Vec<Arc<TaskMetrics>>,
+}
+
+impl Sampler {
+    pub fn new(args: &MetricsArgs, tasks: &[Arc<TaskMetrics>]) -> Option<Self> {
+        args.timeseries.clone().map(|path| Self {
+            interval: args.timeseries_interval.into(),
+            path,
+            tasks: tasks.to_vec(),
+        })
+    }
+
+    pub fn spawn(self) -> SamplerHandle {
+        // Start from now, even if the task is scheduled late
+        let started = Instant::now();
+        let snapshot = MetricsSnapshot::collect(&self.tasks);
+
+        let (stop, stop_rx) = oneshot::channel();
+        SamplerHandle {
+            handle: spawn(self.loop_until(started, snapshot, stop_rx)),
+            stop,
+        }
+    }
+
+    /// Samples on every interval until stopped, and then the last partial one.
+    async fn loop_until(
+        self,
+        started: Instant,
+        mut previous: MetricsSnapshot,
+        mut stop: oneshot::Receiver<()>,
+    ) -> Result<()> {
+        let Self {
+            interval,
+            path,
+            tasks,
+        } = self;
+
+        let is_csv = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+        let mut writer = File::create(&path).map(BufWriter::new).map_err(|error| {
+            anyhow!(
+                "failed to create timeseries ({path}): {error}",
+                path = path.display()
+            )
+        })?;
+        if is_csv {
+            writeln!(writer, "{}", Sample::CSV_HEADER)?;
+        }
+
+        let mut last = started;
+        loop {
+            let is_stopped = select! {
+                _ = &mut stop => true,
+                () = sleep_until(last + interval) => false,
+            };
+
+            let now = Instant::now();
+            let current = MetricsSnapshot::collect(&tasks);
+            let samples = Sample::diff(
+                &previous,
+                &current,
+                (now - started).as_secs_f64(),
+                (now - last).as_secs_f64(),
+            );
+            for sample in &samples {
+                if is_csv {
+                    writeln!(writer, "{}", sample.to_csv())?;
+                } else {
+                    ::serde_json::to_writer(&mut writer, sample)?;
+                    writeln!(writer)?;
+                }
+            }
+            // Let the file be followed during the run
+            writer.flush()?;
+
+            if is_stopped {
+                break Ok(());
+            }
+            previous = current;
+            last = now;
+        }
+    }
+}
+
+pub struct SamplerHandle {
+    handle: JoinHandle<Result<()>>,
+    stop: oneshot::Sender<()>,
+}
+
+impl SamplerHandle {
+    /// Stops the sampler after writing the last interval.
+    pub async fn stop(self) -> Result<()> {
+        let Self { handle, stop } = self;
+        stop.send(()).ok();
+        handle.await?
+    }
+}
+
+/// Metrics of an operation within an interval.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Sample {
+    pub bytes_per_sec: f64,
+    /// Number of the succeeded requests
+    pub count: u64,
+    /// Seconds since the start of the run, at the end of the interval
+    pub elapsed: f64,
+    /// Number of the finally failed requests
+    pub errors: u64,
+    /// Latencies in microseconds
+    pub latency_max: u64,
+    pub latency_p50: u64,
+    pub latency_p90: u64,
+    pub latency_p99: u64,
+    pub operation: Operation,
+    pub ops_per_sec: f64,
+    pub retries: u64,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl Sample {
+    const CSV_HEADER: &'static str = "timestamp,elapsed,operation,count,ops_per_sec,bytes_per_sec,errors,retries,latency_p50_us,latency_p90_us,latency_p99_us,latency_max_us";
+
+    /// Returns the samples of the operations in the interval between the snapshots.
+    pub fn diff(
+        previous: &MetricsSnapshot,
+        current: &MetricsSnapshot,
+        elapsed: f64,
+        interval: f64,
+    ) -> Vec<Self> {
+        let timestamp = Utc::now();
+        let per_sec = |value: u64| {
+            if interval > 0.0 {
+                value as f64 / interval
+            } else {
+                0.0
+            }
+        };
+
+        current
+            .operations
+            .iter()
+            .map(|(operation, current)| {
+                let mut delta = current.clone();
+                if let Some(previous) = previous.operations.get(operation) {
+                    delta.subtract(previous);
+                }
+                let OperationMetrics { bytes, latency, .. } = &delta;
+                Self {
+                    bytes_per_sec: per_sec(*bytes),
+                    count: latency.len(),
+                    elapsed,
+                    errors: delta.total_errors(),
+                    latency_max: latency.max(),
+                    latency_p50: latency.value_at_quantile(0.5),
+                    latency_p90: latency.value_at_quantile(0.9),
+                    latency_p99: latency.value_at_quantile(0.99),
+                    operation: *operation,
+                    ops_per_sec: per_sec(latency.len()),
+                    retries: delta.total_retries(),
+                    timestamp,
+                }
+            })
+            .collect()
+    }
+
+    fn to_csv(&self) -> String {
+        let Self {
+            bytes_per_sec,
+            count,
+            elapsed,
+            errors,
+            latency_max,
+            latency_p50,
+            latency_p90,
+            latency_p99,
+            operation,
+            ops_per_sec,
+            retries,
+            timestamp,
+        } = self;
+
+        format!(
+            "{timestamp},{elapsed:.3},{operation},{count},{ops_per_sec:.3},{bytes_per_sec:.3},{errors},{retries},{latency_p50},{latency_p90},{latency_p99},{latency_max}",
+            timestamp = timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_interval_only() {
+        let task = TaskMetrics::default();
+        for latency in 1..=10 {
+            task.begin();
+            task.record(Operation::Get, Duration::from_millis(latency), 100);
+        }
+        let previous = task.snapshot();
+
+        for _ in 0..4 {
+            task.begin();
+            task.record(Operation::Get, Duration::from_millis(50), 100);
+        }
+        let samples = Sample::diff(&previous, &task.snapshot(), 3.0, 2.0);
+
+        assert_eq!(samples.len(), 1);
+        let sample = &samples[0];
+        assert_eq!(sample.count, 4);
+        assert_eq!(sample.ops_per_sec, 2.0);
+        assert_eq!(sample.bytes_per_sec, 200.0);
+        assert_eq!(sample.latency_p50 / 1000, 50);
+        assert_eq!(sample.latency_max / 1000, 50);
+        assert_eq!(
+            sample.to_csv().split(',').count(),
+            Sample::CSV_HEADER.split(',').count(),
+        );
+    }
+}
@@ -0,0 +1,37 @@
+use std::{error::Error, fmt};
+
+/// Integrity violation of an object read back from the storage.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Corruption {
+    /// The content differs from the written payload
+    Mismatch,
+    /// The object is shorter than the written payload
+    Truncated,
+    /// The object is longer than the written payload
+    WrongSize,
+}
+
+impl Corruption {
+    /// Checks the length of the object, assuming its content has been matched.
+    pub const fn check_len(expected: usize, actual: usize) -> Result<(), Self> {
+        if actual < expected {
+            Err(Self::Truncated)
+        } else if actual > expected {
+            Err(Self::WrongSize)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl fmt::Display for Corruption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mismatch => f.write_str("corrupted object: content mismatch"),
+            Self::Truncated => f.write_str("corrupted object: truncated"),
+            Self::WrongSize => f.write_str("corrupted object: wrong size"),
+        }
+    }
+}
+
+impl Error for Corruption {}
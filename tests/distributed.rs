@@ -0,0 +1,221 @@
+use std::{
+    fs,
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+const BUCKET: &str = "sos-test";
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Runs a controller and its workers as the local processes, storing the objects on the `fs` backend.
+struct Cluster {
+    controller: Child,
+    root: PathBuf,
+    workers: Vec<Child>,
+}
+
+impl Cluster {
+    fn spawn(name: &str, workers: usize, extra: &[&str]) -> Self {
+        Self::spawn_staggered(name, workers, Duration::ZERO, extra)
+    }
+
+    /// Spawns the workers one by one, the given delay apart.
+    fn spawn_staggered(name: &str, workers: usize, delay: Duration, extra: &[&str]) -> Self {
+        let root = std::env::temp_dir().join(format!(
+            "sos-distributed-{name}-{id}",
+            id = std::process::id(),
+        ));
+        fs::remove_dir_all(&root).ok();
+        fs::create_dir_all(root.join(BUCKET)).unwrap();
+
+        // Reserve a free port for the controller
+        let address = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string();
+
+        let backend = format!("fs:{}", root.display());
+        let workers_arg = workers.to_string();
+        let mut args = vec![
+            "--backend",
+            &backend,
+            "--bucket-name",
+            BUCKET,
+            "--controller-address",
+            &address,
+            "--no-progress-bar",
+            "--role",
+            "controller",
+            "--workers",
+            &workers_arg,
+        ];
+        args.extend_from_slice(extra);
+        let controller = sos(&args);
+
+        let url = format!("http://{address}");
+        let workers = (0..workers)
+            .map(|id| {
+                if id > 0 {
+                    sleep(delay);
+                }
+                sos(&["--controller-url", &url, "--role", "worker"])
+            })
+            .collect();
+
+        Self {
+            controller,
+            root,
+            workers,
+        }
+    }
+
+    /// Waits for all processes, returning the exit status of the controller and the workers.
+    fn wait(&mut self) -> (ExitStatus, Vec<ExitStatus>) {
+        let controller = wait(&mut self.controller);
+        let workers = self.workers.iter_mut().map(wait).collect();
+        (controller, workers)
+    }
+
+    fn objects(&self) -> usize {
+        count_files(&self.root.join(BUCKET))
+    }
+}
+
+impl Drop for Cluster {
+    fn drop(&mut self) {
+        for child in std::iter::once(&mut self.controller).chain(&mut self.workers) {
+            child.kill().ok();
+        }
+        fs::remove_dir_all(&self.root).ok();
+    }
+}
+
+fn sos(args: &[&str]) -> Child {
+    Command::new(env!("CARGO_BIN_EXE_sos"))
+        .args(args)
+        .spawn()
+        .unwrap()
+}
+
+fn wait(child: &mut Child) -> ExitStatus {
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        assert!(Instant::now() < deadline, "timed out waiting for sos");
+        sleep(Duration::from_millis(50));
+    }
+}
+
+fn count_files(dir: &Path) -> usize {
+    fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| !path.ends_with(".sos-multipart"))
+        .map(|path| if path.is_dir() { count_files(&path) } else { 1 })
+        .sum()
+}
+
+#[test]
+fn workers_split_key_space() {
+    let mut cluster = Cluster::spawn(
+        "split",
+        2,
+        &[
+            "--count",
+            "16",
+            "--no-cleanup",
+            "--size",
+            "1KiB",
+            "--slo",
+            "error_rate(put) < 1%",
+            "--step",
+            "8",
+        ],
+    );
+
+    let (controller, workers) = cluster.wait();
+    assert!(controller.success());
+    assert!(workers.iter().all(ExitStatus::success));
+
+    // Each worker writes its own objects
+    assert_eq!(cluster.objects(), 2 * 8);
+}
+
+#[test]
+fn controller_waits_for_late_workers() {
+    // The early worker has registered longer than the timeout before the start
+    let mut cluster = Cluster::spawn_staggered(
+        "late",
+        2,
+        Duration::from_secs(5),
+        &[
+            "--count",
+            "16",
+            "--size",
+            "1KiB",
+            "--step",
+            "8",
+            "--worker-timeout",
+            "3s",
+        ],
+    );
+
+    let (controller, workers) = cluster.wait();
+    assert!(controller.success());
+    assert!(workers.iter().all(ExitStatus::success));
+}
+
+#[test]
+fn controller_cleans_up_after_workers() {
+    let mut cluster = Cluster::spawn(
+        "cleanup",
+        3,
+        &["--count", "16", "--size", "1KiB", "--step", "8"],
+    );
+
+    let (controller, workers) = cluster.wait();
+    assert!(controller.success());
+    assert!(workers.iter().all(ExitStatus::success));
+    assert_eq!(cluster.objects(), 0);
+}
+
+#[test]
+fn controller_fails_with_worker() {
+    // A single worker, as the controller may stop before the others have registered
+    let mut cluster = Cluster::spawn("fail", 1, &["--ranges-per-object", "0"]);
+
+    let (controller, workers) = cluster.wait();
+    assert!(!controller.success());
+    assert!(workers.iter().all(|status| !status.success()));
+}
+
+#[test]
+fn controller_exits_on_slo_violation() {
+    let mut cluster = Cluster::spawn(
+        "slo",
+        2,
+        &[
+            "--count",
+            "16",
+            "--size",
+            "1KiB",
+            "--slo",
+            "p99(put) < 1h,rate(put) > 1000000000/s",
+            "--step",
+            "8",
+        ],
+    );
+
+    let (controller, workers) = cluster.wait();
+    assert_eq!(controller.code(), Some(2));
+    assert!(workers.iter().all(ExitStatus::success));
+
+    // The objects are cleaned up anyway
+    assert_eq!(cluster.objects(), 0);
+}
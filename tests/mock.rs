@@ -0,0 +1,612 @@
+use std::time::Duration;
+
+use ark_core::signal::FunctionSignal;
+use clap::Parser;
+use stressful_object_storage::{
+    args::{Args, Operation},
+    backend::{self, Backend},
+    metrics::{ErrorKind, MetricsSnapshot},
+    mock::{MockOptions, MockServer},
+    session::ObjectStorageSession,
+};
+
+const BUCKET: &str = "sos-test";
+
+async fn spawn_server() -> MockServer {
+    let server = MockServer::try_spawn(MockOptions::default()).await.unwrap();
+    server.create_bucket(BUCKET);
+    server
+}
+
+fn args(server: &MockServer, extra: &[&str]) -> Args {
+    let endpoint = server.endpoint();
+    let mut argv = vec![
+        "sos",
+        "--access-key",
+        MockServer::ACCESS_KEY,
+        "--bucket-name",
+        BUCKET,
+        "--endpoint",
+        &endpoint,
+        "--no-progress-bar",
+        "--secret-key",
+        MockServer::SECRET_KEY,
+    ];
+    argv.extend_from_slice(extra);
+    Args::try_parse_from(argv).unwrap()
+}
+
+async fn run(args: Args) -> (anyhow::Result<()>, MetricsSnapshot) {
+    let session = ObjectStorageSession::try_new(args).await.unwrap();
+    let metrics = session.task_metrics().to_vec();
+    let result = session.try_loop_forever(FunctionSignal::default()).await;
+    (result, MetricsSnapshot::collect(&metrics))
+}
+
+fn count(snapshot: &MetricsSnapshot, operation: Operation) -> u64 {
+    snapshot
+        .operations
+        .get(&operation)
+        .map(|metrics| metrics.latency.len())
+        .unwrap_or_default()
+}
+
+#[tokio::test]
+async fn s3_backend_roundtrip() {
+    let server = spawn_server().await;
+    let backend = backend::try_new(&args(&server, &[])).await.unwrap();
+
+    backend.put("/a/1.bin", b"hello world").await.unwrap();
+    backend.put("/b/1.bin", b"!").await.unwrap();
+    assert_eq!(backend.head("/a/1.bin").await.unwrap(), 11);
+
+    let mut buf = vec![];
+    backend
+        .get("/a/1.bin", Some(6..11), &mut buf)
+        .await
+        .unwrap();
+    assert_eq!(buf, b"world");
+    let error = backend
+        .get("/a/1.bin", Some(11..12), &mut buf)
+        .await
+        .unwrap_err();
+    assert!(backend::is_range_not_satisfiable(&error));
+
+    let page = backend.list("", Some("/"), None, 1).await.unwrap();
+    assert_eq!(page.prefixes, ["a/"]);
+    let page = backend
+        .list("", Some("/"), page.next_token, 1)
+        .await
+        .unwrap();
+    assert_eq!(page.prefixes, ["b/"]);
+    assert_eq!(page.next_token, None);
+
+    let upload_id = backend.create_multipart("/c/1.bin").await.unwrap();
+    let mut parts = vec![];
+    for (part_number, data) in [b"foo", b"bar"].into_iter().enumerate() {
+        let part = backend
+            .upload_part("/c/1.bin", &upload_id, part_number as u32 + 1, data)
+            .await
+            .unwrap();
+        parts.push(part);
+    }
+    backend
+        .complete_multipart("/c/1.bin", &upload_id, parts)
+        .await
+        .unwrap();
+
+    let mut buf = vec![];
+    backend.get("/c/1.bin", None, &mut buf).await.unwrap();
+    assert_eq!(buf, b"foobar");
+
+    backend.delete("/a/1.bin").await.unwrap();
+    assert!(backend.head("/a/1.bin").await.is_err());
+
+    backend
+        .delete_many(&["/b/1.bin".into(), "/c/1.bin".into()])
+        .await
+        .unwrap();
+    assert!(backend.head("/b/1.bin").await.is_err());
+    assert!(backend.head("/c/1.bin").await.is_err());
+}
+
+#[tokio::test]
+async fn bucket_create() {
+    let server = MockServer::try_spawn(MockOptions::default()).await.unwrap();
+    assert!(backend::try_new(&args(&server, &[])).await.is_err());
+    assert!(server.bucket(BUCKET).is_none());
+
+    backend::try_new(&args(&server, &["--bucket-create"]))
+        .await
+        .unwrap();
+    assert!(server.bucket(BUCKET).is_some());
+}
+
+#[tokio::test]
+async fn bucket_rejects_wrong_signatures() {
+    let server = spawn_server().await;
+    let mut args = args(&server, &[]);
+    args.credentials.secret_key = Some("wrong".into());
+
+    assert!(backend::try_new(&args).await.is_err());
+    assert!(server.requests() > 0);
+    assert_eq!(server.injected_errors(), 0);
+}
+
+#[tokio::test]
+async fn session_write_and_cleanup() {
+    let server = spawn_server().await;
+    let args = args(
+        &server,
+        &[
+            "--count",
+            "32",
+            "--size",
+            "4KiB",
+            "--step",
+            "8",
+            "--threads-max",
+            "2",
+        ],
+    );
+
+    let (result, snapshot) = run(args).await;
+    result.unwrap();
+    assert_eq!(count(&snapshot, Operation::Put), 32);
+
+    // The written objects are removed after the run
+    let page = server
+        .bucket(BUCKET)
+        .unwrap()
+        .list("", None, None, 1000)
+        .await
+        .unwrap();
+    assert!(page.keys.is_empty(), "{:?}", page.keys);
+}
+
+#[tokio::test]
+async fn session_read_and_verify() {
+    let server = spawn_server().await;
+    let args = args(
+        &server,
+        &[
+            "--count",
+            "16",
+            "--mode",
+            "read",
+            "--size-distribution",
+            "uniform:1KiB..64KiB",
+            "--step",
+            "4",
+            "--verify",
+        ],
+    );
+
+    let (result, snapshot) = run(args).await;
+    result.unwrap();
+    assert_eq!(count(&snapshot, Operation::Get), 16);
+    assert_eq!(snapshot.total_corruptions(), 0);
+}
+
+#[tokio::test]
+async fn session_multipart_ranged_reads() {
+    let server = spawn_server().await;
+    let args = args(
+        &server,
+        &[
+            "--count",
+            "4",
+            "--mode",
+            "read",
+            "--multipart-concurrency",
+            "2",
+            "--multipart-threshold",
+            "5MiB",
+            "--range-size",
+            "1MiB",
+            "--ranges-per-object",
+            "2",
+            "--size",
+            "12MiB",
+            "--step",
+            "2",
+            "--verify",
+        ],
+    );
+
+    let (result, snapshot) = run(args).await;
+    result.unwrap();
+    assert_eq!(count(&snapshot, Operation::Get), 4);
+    assert_eq!(
+        snapshot.operations[&Operation::Get].bytes,
+        4 * 2 * 1024 * 1024
+    );
+    assert_eq!(snapshot.total_corruptions(), 0);
+}
+
+#[tokio::test]
+async fn session_deletes_key_space_in_batches() {
+    let server = spawn_server().await;
+    let args = args(
+        &server,
+        &[
+            "--delete-batch",
+            "4",
+            "--key-distribution",
+            "uniform",
+            "--mode",
+            "delete",
+            "--no-cleanup",
+            "--size",
+            "1KiB",
+            "--step",
+            "16",
+        ],
+    );
+
+    let (result, snapshot) = run(args).await;
+    result.unwrap();
+    assert_eq!(count(&snapshot, Operation::Delete), 16);
+
+    // Every key has been deleted once, whatever the distribution
+    let page = server
+        .bucket(BUCKET)
+        .unwrap()
+        .list("", None, None, 1000)
+        .await
+        .unwrap();
+    assert!(page.keys.is_empty(), "{:?}", page.keys);
+}
+
+#[tokio::test]
+async fn session_fails_on_injected_errors() {
+    let server = spawn_server().await;
+    let args = args(&server, &["--count", "8", "--size", "1KiB", "--step", "4"]);
+    let session = ObjectStorageSession::try_new(args).await.unwrap();
+    let metrics = session.task_metrics().to_vec();
+
+    server.set_options(MockOptions {
+        error_rate: 1.0,
+        ..Default::default()
+    });
+    let result = session.try_loop_forever(FunctionSignal::default()).await;
+    assert!(result.is_err());
+    assert!(server.injected_errors() > 0);
+
+    let snapshot = MetricsSnapshot::collect(&metrics);
+    let errors = &snapshot.operations[&Operation::Put].errors;
+    assert!(errors.get(&ErrorKind::Server).copied().unwrap_or_default() > 0);
+}
+
+#[tokio::test]
+async fn session_with_injected_latency() {
+    const LATENCY: Duration = Duration::from_millis(20);
+
+    let server = spawn_server().await;
+    server.set_options(MockOptions {
+        latency: LATENCY,
+        ..Default::default()
+    });
+    let args = args(&server, &["--count", "4", "--size", "1KiB", "--step", "4"]);
+
+    let (result, snapshot) = run(args).await;
+    result.unwrap();
+    let latency = &snapshot.operations[&Operation::Put].latency;
+    assert!(latency.min() >= LATENCY.as_micros() as u64);
+}
+
+#[tokio::test]
+async fn session_retries_injected_errors() {
+    let server = spawn_server().await;
+    let args = args(
+        &server,
+        &[
+            "--count",
+            "32",
+            // The cleanup is not retried
+            "--no-cleanup",
+            "--retry-backoff",
+            "1ms",
+            "--retry-max-attempts",
+            "20",
+            "--size",
+            "1KiB",
+            "--step",
+            "8",
+        ],
+    );
+    let session = ObjectStorageSession::try_new(args).await.unwrap();
+    let metrics = session.task_metrics().to_vec();
+
+    server.set_options(MockOptions {
+        error_rate: 0.2,
+        ..Default::default()
+    });
+    session
+        .try_loop_forever(FunctionSignal::default())
+        .await
+        .unwrap();
+
+    let snapshot = MetricsSnapshot::collect(&metrics);
+    let put = &snapshot.operations[&Operation::Put];
+    assert_eq!(put.latency.len(), 32);
+    assert_eq!(put.total_errors(), 0);
+    assert!(
+        put.retries
+            .get(&ErrorKind::Server)
+            .copied()
+            .unwrap_or_default()
+            > 0
+    );
+}
+
+#[tokio::test]
+async fn session_continues_within_error_budget() {
+    let server = spawn_server().await;
+    let args = args(
+        &server,
+        &[
+            "--count",
+            "200",
+            "--error-budget",
+            "50",
+            "--no-cleanup",
+            "--size",
+            "1KiB",
+            "--step",
+            "8",
+        ],
+    );
+    let session = ObjectStorageSession::try_new(args).await.unwrap();
+    let metrics = session.task_metrics().to_vec();
+
+    server.set_options(MockOptions {
+        error_rate: 0.1,
+        ..Default::default()
+    });
+    session
+        .try_loop_forever(FunctionSignal::default())
+        .await
+        .unwrap();
+
+    let snapshot = MetricsSnapshot::collect(&metrics);
+    assert!(snapshot.operations[&Operation::Put].total_errors() > 0);
+}
+
+#[tokio::test]
+async fn session_fails_over_error_budget() {
+    let server = spawn_server().await;
+    let args = args(
+        &server,
+        &["--count", "200", "--error-budget", "1", "--size", "1KiB"],
+    );
+    let session = ObjectStorageSession::try_new(args).await.unwrap();
+
+    server.set_options(MockOptions {
+        error_rate: 1.0,
+        ..Default::default()
+    });
+    let error = session
+        .try_loop_forever(FunctionSignal::default())
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("error budget"), "{error}");
+}
+
+#[tokio::test]
+async fn session_times_out_slow_requests() {
+    let server = spawn_server().await;
+    let args = args(
+        &server,
+        &[
+            "--count",
+            "4",
+            "--request-timeout",
+            "10ms",
+            "--retry-max-attempts",
+            "2",
+            "--size",
+            "1KiB",
+            "--step",
+            "4",
+        ],
+    );
+    let session = ObjectStorageSession::try_new(args).await.unwrap();
+    let metrics = session.task_metrics().to_vec();
+
+    server.set_options(MockOptions {
+        latency: Duration::from_millis(200),
+        ..Default::default()
+    });
+    let result = session.try_loop_forever(FunctionSignal::default()).await;
+    assert!(result.is_err());
+
+    let snapshot = MetricsSnapshot::collect(&metrics);
+    let put = &snapshot.operations[&Operation::Put];
+    assert!(
+        put.errors
+            .get(&ErrorKind::Timeout)
+            .copied()
+            .unwrap_or_default()
+            > 0
+    );
+    assert!(
+        put.retries
+            .get(&ErrorKind::Timeout)
+            .copied()
+            .unwrap_or_default()
+            > 0
+    );
+}
+
+#[tokio::test]
+async fn session_aborts_timed_out_multipart_uploads() {
+    const LATENCY: Duration = Duration::from_millis(300);
+
+    let server = spawn_server().await;
+    let args = args(
+        &server,
+        &[
+            "--count",
+            "1",
+            "--multipart-threshold",
+            "5MiB",
+            "--request-timeout",
+            "500ms",
+            "--retry-max-attempts",
+            "1",
+            "--size",
+            "12MiB",
+            "--step",
+            "1",
+        ],
+    );
+    let session = ObjectStorageSession::try_new(args).await.unwrap();
+
+    // The upload is created in time, but its parts are not
+    server.set_options(MockOptions {
+        latency: LATENCY,
+        ..Default::default()
+    });
+    let result = session.try_loop_forever(FunctionSignal::default()).await;
+    assert!(result.is_err());
+
+    // The upload is aborted in the background
+    tokio::time::sleep(LATENCY * 3).await;
+    assert_eq!(server.bucket(BUCKET).unwrap().uploads(), 0);
+}
+
+#[tokio::test]
+async fn session_keeps_slow_connected_requests() {
+    const LATENCY: Duration = Duration::from_millis(300);
+
+    let server = spawn_server().await;
+    let args = args(
+        &server,
+        &[
+            "--connect-timeout",
+            "50ms",
+            "--count",
+            "4",
+            "--request-timeout",
+            "1s",
+            "--retry-max-attempts",
+            "1",
+            "--size",
+            "1KiB",
+            "--step",
+            "4",
+        ],
+    );
+    let session = ObjectStorageSession::try_new(args).await.unwrap();
+    let metrics = session.task_metrics().to_vec();
+
+    // The responses are bounded by the request timeout, not the connect timeout
+    server.set_options(MockOptions {
+        latency: LATENCY,
+        ..Default::default()
+    });
+    session
+        .try_loop_forever(FunctionSignal::default())
+        .await
+        .unwrap();
+
+    let snapshot = MetricsSnapshot::collect(&metrics);
+    let put = &snapshot.operations[&Operation::Put];
+    assert_eq!(put.latency.len(), 4);
+    assert!(put.latency.min() >= LATENCY.as_micros() as u64);
+}
+
+#[tokio::test]
+async fn session_writes_report_on_failure() {
+    let server = spawn_server().await;
+    let path = std::env::temp_dir().join(format!("sos-report-{}.json", std::process::id()));
+    let report_arg = path.display().to_string();
+    let args = args(
+        &server,
+        &[
+            "--count",
+            "8",
+            "--report",
+            &report_arg,
+            "--size",
+            "1KiB",
+            "--step",
+            "4",
+        ],
+    );
+    let session = ObjectStorageSession::try_new(args).await.unwrap();
+
+    server.set_options(MockOptions {
+        error_rate: 1.0,
+        ..Default::default()
+    });
+    let result = session.try_loop_forever(FunctionSignal::default()).await;
+    assert!(result.is_err());
+
+    let report: serde_json::Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    std::fs::remove_file(&path).ok();
+    assert_eq!(report["args"]["accessKey"], "(hidden)");
+    assert_eq!(report["args"]["report"], report_arg);
+    assert!(report["error"].is_string());
+    assert!(report["startedAt"].as_str() <= report["finishedAt"].as_str());
+    assert_eq!(report["operations"][0]["operation"], "put");
+    assert!(
+        report["operations"][0]["errors"]["server"]
+            .as_u64()
+            .unwrap()
+            > 0
+    );
+}
+
+#[tokio::test]
+async fn session_writes_timeseries() {
+    const LATENCY: Duration = Duration::from_millis(20);
+
+    let server = spawn_server().await;
+    server.set_options(MockOptions {
+        latency: LATENCY,
+        ..Default::default()
+    });
+    let path = std::env::temp_dir().join(format!("sos-timeseries-{}.csv", std::process::id()));
+    let timeseries_arg = path.display().to_string();
+    let args = args(
+        &server,
+        &[
+            "--count",
+            "16",
+            "--size",
+            "1KiB",
+            "--step",
+            "4",
+            "--threads-max",
+            "1",
+            "--timeseries",
+            &timeseries_arg,
+            "--timeseries-interval",
+            "100ms",
+        ],
+    );
+
+    let (result, snapshot) = run(args).await;
+    result.unwrap();
+
+    let timeseries = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).ok();
+    let mut lines = timeseries.lines();
+    let header = lines.next().unwrap().split(',').collect::<Vec<_>>();
+    let rows = lines
+        .map(|line| line.split(',').collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert!(rows.len() >= 2, "{timeseries}");
+
+    let column = |name| header.iter().position(|column| *column == name).unwrap();
+    assert!(rows.iter().all(|row| row[column("operation")] == "put"));
+
+    // The intervals add up to the whole run
+    let total = rows
+        .iter()
+        .map(|row| row[column("count")].parse::<u64>().unwrap())
+        .sum::<u64>();
+    assert_eq!(total, count(&snapshot, Operation::Put));
+}
@@ -0,0 +1,346 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    body::Bytes,
+    extract::{Query, State},
+    http::{Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+use kube::{runtime::controller::Action, Client, Config};
+use serde_json::{json, Value};
+use stressful_object_storage::operator::{
+    reconcile, Context, StressTest, StressTestPhase, StressTestStatus,
+};
+use tokio::{net::TcpListener, spawn};
+
+const NAMESPACE: &str = "default";
+
+const CONTROLLER_PATH: &str = "/apis/batch/v1/namespaces/default/jobs/bench-controller";
+const SECRET_PATH: &str = "/api/v1/namespaces/default/secrets/bench-scenario";
+const SERVICE_PATH: &str = "/api/v1/namespaces/default/services/bench-controller";
+const TEST_PATH: &str = "/apis/sos.ulagbulag.io/v1alpha1/namespaces/default/stresstests/bench";
+const WORKERS_PATH: &str = "/apis/batch/v1/namespaces/default/jobs/bench-workers";
+
+/// Stand-in of the kubernetes API server, storing the objects by their paths.
+#[derive(Clone, Default)]
+struct ApiServer {
+    objects: Arc<Mutex<BTreeMap<String, Value>>>,
+}
+
+impl ApiServer {
+    async fn spawn(&self) -> Client {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let app = Router::new().fallback(serve).with_state(self.clone());
+        spawn(async move { ::axum::serve(listener, app).await });
+
+        let mut config = Config::new(format!("http://{address}").parse().unwrap());
+        config.default_namespace = NAMESPACE.into();
+        Client::try_from(config).unwrap()
+    }
+
+    fn get(&self, path: &str) -> Value {
+        self.objects.lock().unwrap()[path].clone()
+    }
+
+    fn insert(&self, path: &str, object: Value) {
+        self.objects.lock().unwrap().insert(path.into(), object);
+    }
+
+    /// Merges the fields into the stored object, e.g. the status set by the other controllers.
+    fn merge(&self, path: &str, patch: Value) {
+        let mut objects = self.objects.lock().unwrap();
+        merge(objects.get_mut(path).unwrap(), patch);
+    }
+}
+
+async fn serve(
+    State(server): State<ApiServer>,
+    method: Method,
+    uri: Uri,
+    Query(query): Query<BTreeMap<String, String>>,
+    body: Bytes,
+) -> Response {
+    let path = uri.path().to_string();
+    let mut objects = server.objects.lock().unwrap();
+
+    match method {
+        Method::GET => match objects.get(&path) {
+            Some(object) => Json(object.clone()).into_response(),
+            None => {
+                // List the objects in the collection
+                let selector = query.get("labelSelector").cloned().unwrap_or_default();
+                let items = objects
+                    .iter()
+                    .filter(|(key, _)| {
+                        key.strip_prefix(&path)
+                            .and_then(|name| name.strip_prefix('/'))
+                            .is_some_and(|name| !name.contains('/'))
+                    })
+                    .map(|(_, object)| object.clone())
+                    .filter(|object| matches_labels(object, &selector))
+                    .collect::<Vec<_>>();
+                Json(json!({
+                    "apiVersion": "v1",
+                    "kind": "List",
+                    "metadata": {},
+                    "items": items,
+                }))
+                .into_response()
+            }
+        },
+        Method::PATCH => {
+            let patch: Value = ::serde_json::from_slice(&body).unwrap();
+            let object = match path.strip_suffix("/status") {
+                // Merge patch of the status
+                Some(parent) => {
+                    let object = objects.get_mut(parent).unwrap();
+                    merge(object, patch);
+                    object.clone()
+                }
+                // Server-side apply, keeping the status
+                None => {
+                    let mut object = patch;
+                    if let Some(status) = objects.get(&path).and_then(|object| object.get("status"))
+                    {
+                        object["status"] = status.clone();
+                    }
+                    objects.insert(path, object.clone());
+                    object
+                }
+            };
+            Json(object).into_response()
+        }
+        _ => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+    }
+}
+
+fn matches_labels(object: &Value, selector: &str) -> bool {
+    selector
+        .split(',')
+        .filter(|term| !term.is_empty())
+        .all(|term| {
+            let (key, value) = term.split_once('=').unwrap();
+            object["metadata"]["labels"][key] == value
+        })
+}
+
+fn merge(object: &mut Value, patch: Value) {
+    match (object, patch) {
+        (Value::Object(object), Value::Object(patch)) => {
+            for (key, value) in patch {
+                merge(object.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (object, patch) => *object = patch,
+    }
+}
+
+fn stress_test() -> Value {
+    json!({
+        "apiVersion": "sos.ulagbulag.io/v1alpha1",
+        "kind": "StressTest",
+        "metadata": {
+            "name": "bench",
+            "namespace": NAMESPACE,
+            "uid": "5d6c1c5e-0000-4000-8000-000000000000",
+        },
+        "spec": {
+            "args": {
+                "backend": "memory",
+                "bucketName": "sos-test",
+                "count": "64",
+                "size": "1KiB",
+            },
+            "envFrom": [{
+                "secretRef": { "name": "credentials" },
+            }],
+            "workers": 3,
+        },
+    })
+}
+
+async fn reconcile_test(server: &ApiServer, client: &Client) -> Action {
+    let test: StressTest = ::serde_json::from_value(server.get(TEST_PATH)).unwrap();
+    let context = Arc::new(Context {
+        client: client.clone(),
+    });
+    reconcile(Arc::new(test), context).await.unwrap()
+}
+
+fn status(server: &ApiServer) -> StressTestStatus {
+    ::serde_json::from_value(server.get(TEST_PATH)["status"].clone()).unwrap()
+}
+
+fn insert_controller_pod(server: &ApiServer, message: &str) {
+    server.insert(
+        "/api/v1/namespaces/default/pods/bench-controller-x7k2p",
+        json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {
+                "name": "bench-controller-x7k2p",
+                "namespace": NAMESPACE,
+                "labels": {
+                    "app.kubernetes.io/component": "controller",
+                    "app.kubernetes.io/instance": "bench",
+                },
+            },
+            "status": {
+                "containerStatuses": [{
+                    "image": "quay.io/ulagbulag/sos:latest",
+                    "imageID": "",
+                    "name": "sos",
+                    "ready": false,
+                    "restartCount": 0,
+                    "state": {
+                        "terminated": {
+                            "exitCode": 0,
+                            "message": message,
+                        },
+                    },
+                }],
+            },
+        }),
+    );
+}
+
+fn container_args(job: &Value) -> Vec<String> {
+    ::serde_json::from_value(job["spec"]["template"]["spec"]["containers"][0]["args"].clone())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn reconcile_creates_jobs() {
+    let server = ApiServer::default();
+    let client = server.spawn().await;
+    server.insert(TEST_PATH, stress_test());
+
+    reconcile_test(&server, &client).await;
+
+    // The scenario is kept in a secret for the credentials
+    let secret = server.get(SECRET_PATH);
+    let scenario: Value =
+        ::serde_json::from_str(secret["stringData"]["scenario.json"].as_str().unwrap()).unwrap();
+    assert_eq!(scenario["bucketName"], "sos-test");
+    assert_eq!(scenario["backend"], "memory");
+
+    let controller = server.get(CONTROLLER_PATH);
+    let args = container_args(&controller);
+    assert!(args.windows(2).any(|arg| arg == ["--role", "controller"]));
+    assert!(args.windows(2).any(|arg| arg == ["--workers", "3"]));
+    assert_eq!(
+        controller["metadata"]["ownerReferences"][0]["name"],
+        "bench"
+    );
+
+    let workers = server.get(WORKERS_PATH);
+    assert_eq!(workers["spec"]["parallelism"], 3);
+    assert_eq!(workers["spec"]["completions"], 3);
+    assert_eq!(
+        container_args(&workers),
+        [
+            "--controller-url",
+            "http://bench-controller:9900",
+            "--role",
+            "worker",
+        ],
+    );
+    assert_eq!(
+        workers["spec"]["template"]["spec"]["containers"][0]["envFrom"][0]["secretRef"]["name"],
+        "credentials",
+    );
+
+    let service = server.get(SERVICE_PATH);
+    assert_eq!(service["spec"]["ports"][0]["port"], 9900);
+    assert_eq!(
+        service["spec"]["selector"]["app.kubernetes.io/component"],
+        "controller",
+    );
+
+    assert_eq!(status(&server).phase, StressTestPhase::Pending);
+}
+
+#[tokio::test]
+async fn reconcile_writes_results() {
+    let server = ApiServer::default();
+    let client = server.spawn().await;
+    server.insert(TEST_PATH, stress_test());
+    reconcile_test(&server, &client).await;
+
+    server.merge(CONTROLLER_PATH, json!({ "status": { "active": 1 } }));
+    reconcile_test(&server, &client).await;
+    assert_eq!(status(&server).phase, StressTestPhase::Running);
+
+    // The controller leaves the aggregated results in its termination message
+    let results = json!([{
+        "bytes": 65536,
+        "count": 64,
+        "errors": 0,
+        "latencyMax": 900,
+        "latencyP50": 100,
+        "latencyP90": 200,
+        "latencyP99": 800,
+        "operation": "put",
+        "retries": 1,
+    }]);
+    insert_controller_pod(&server, &results.to_string());
+    server.merge(
+        CONTROLLER_PATH,
+        json!({ "status": { "active": 0, "succeeded": 1 } }),
+    );
+    let action = reconcile_test(&server, &client).await;
+    assert_eq!(action, Action::await_change());
+
+    let status = status(&server);
+    assert_eq!(status.phase, StressTestPhase::Succeeded);
+    assert_eq!(status.results.len(), 1);
+    assert_eq!(status.results[0].count, 64);
+    assert_eq!(status.results[0].latency_p99, 800);
+}
+
+#[tokio::test]
+async fn reconcile_reports_failure() {
+    let server = ApiServer::default();
+    let client = server.spawn().await;
+    server.insert(TEST_PATH, stress_test());
+    reconcile_test(&server, &client).await;
+
+    server.merge(CONTROLLER_PATH, json!({ "status": { "failed": 1 } }));
+    reconcile_test(&server, &client).await;
+
+    let status = status(&server);
+    assert_eq!(status.phase, StressTestPhase::Failed);
+    assert!(status.message.is_some());
+
+    // Finished tests are never rerun
+    server.merge(CONTROLLER_PATH, json!({ "status": { "active": 1 } }));
+    assert_eq!(
+        reconcile_test(&server, &client).await,
+        Action::await_change()
+    );
+}
+
+#[tokio::test]
+async fn reconcile_reports_truncated_results() {
+    let server = ApiServer::default();
+    let client = server.spawn().await;
+    server.insert(TEST_PATH, stress_test());
+    reconcile_test(&server, &client).await;
+
+    insert_controller_pod(
+        &server,
+        r#"[{"bytes":65536,"count":64,"errors":0,"latencyMax":900,"lat"#,
+    );
+    server.merge(CONTROLLER_PATH, json!({ "status": { "succeeded": 1 } }));
+    reconcile_test(&server, &client).await;
+
+    let status = status(&server);
+    assert_eq!(status.phase, StressTestPhase::Succeeded);
+    assert!(status.results.is_empty());
+    assert!(status.message.unwrap().contains("truncated"));
+}